@@ -0,0 +1,216 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Where `attach` found the app's VM Service.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Given on the command line.
+    Argument,
+    /// Printed in a `flutter run` log file.
+    LogFile,
+    /// Written by a running `flutter run --vmservice-out-file`.
+    RunningProcess,
+    /// Left to `flutter attach` to discover on the device.
+    FlutterAttach,
+}
+
+impl Source {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Source::Argument => "the given URI",
+            Source::LogFile => "the log file",
+            Source::RunningProcess => "a running flutter run",
+            Source::FlutterAttach => "flutter attach",
+        }
+    }
+}
+
+/// Find the VM Service URI to hand to `flutter attach --debug-url`: the given
+/// one, the last one printed in `log`, or one written by a `flutter run` for
+/// the project. None leaves discovery to `flutter attach`.
+pub fn discover(
+    project_dir: &Path,
+    uri: Option<&str>,
+    log: Option<&Path>,
+) -> Result<(Option<String>, Source)> {
+    if let Some(uri) = uri {
+        return Ok((Some(debug_url(uri)), Source::Argument));
+    }
+    if let Some(log) = log {
+        let contents = std::fs::read_to_string(log)
+            .with_context(|| format!("Cannot read {}", log.display()))?;
+        let uri = uri_from_log(&contents)
+            .with_context(|| format!("No VM Service URI in {}", log.display()))?;
+        return Ok((Some(uri), Source::LogFile));
+    }
+
+    for process in flutter_runs(project_dir) {
+        match out_file(&process.args) {
+            Some(file) => {
+                if let Ok(uri) = std::fs::read_to_string(project_dir.join(file))
+                    && !uri.trim().is_empty()
+                {
+                    return Ok((Some(debug_url(uri.trim())), Source::RunningProcess));
+                }
+            }
+            None => eprintln!(
+                "Found flutter run (PID {}) without --vmservice-out-file; \
+                 leaving discovery to flutter attach",
+                process.pid
+            ),
+        }
+    }
+    Ok((None, Source::FlutterAttach))
+}
+
+/// The last VM Service URI printed in a `flutter run` log, from the
+/// "Dart VM Service ... is available at", DevTools `?uri=` or machine-mode
+/// `app.debugPort` lines.
+pub fn uri_from_log(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .rev()
+        .filter(|line| {
+            line.contains("VM Service")
+                || line.contains("VM service")
+                || line.contains("?uri=")
+                || line.contains("\"wsUri\"")
+        })
+        .find_map(last_url)
+        .map(debug_url)
+}
+
+/// The last http:// or ws:// URL in a line.
+fn last_url(line: &str) -> Option<&str> {
+    let start = ["http://", "ws://"]
+        .iter()
+        .filter_map(|scheme| line.rfind(scheme))
+        .max()?;
+    let url = &line[start..];
+    let end = url
+        .find(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == ',')
+        .unwrap_or(url.len());
+    Some(&url[..end])
+}
+
+/// The http form `flutter attach --debug-url` expects:
+/// `ws://127.0.0.1:4021/abc=/ws` becomes `http://127.0.0.1:4021/abc=/`.
+pub fn debug_url(uri: &str) -> String {
+    match uri.strip_prefix("ws://") {
+        Some(rest) => {
+            let rest = rest.strip_suffix("/ws").unwrap_or(rest);
+            let slash = if rest.ends_with('/') { "" } else { "/" };
+            format!("http://{rest}{slash}")
+        }
+        None => uri.to_string(),
+    }
+}
+
+struct FlutterRun {
+    pid: u32,
+    args: Vec<String>,
+}
+
+/// `flutter run` processes whose working directory is the project.
+fn flutter_runs(project_dir: &Path) -> Vec<FlutterRun> {
+    let Ok(project_dir) = project_dir.canonicalize() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let cmdline = std::fs::read(entry.path().join("cmdline")).ok()?;
+            let args: Vec<String> = cmdline
+                .split(|&b| b == 0)
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            if !is_flutter_run(&args) {
+                return None;
+            }
+            let cwd: PathBuf = std::fs::read_link(entry.path().join("cwd")).ok()?;
+            (cwd == project_dir).then_some(FlutterRun { pid, args })
+        })
+        .collect()
+}
+
+/// Whether a command line is the flutter tool running `run`.
+fn is_flutter_run(args: &[String]) -> bool {
+    args.iter().any(|arg| arg.contains("flutter_tools")) && args.iter().any(|arg| arg == "run")
+}
+
+/// The `--vmservice-out-file` a `flutter run` writes its VM Service URI to.
+fn out_file(args: &[String]) -> Option<&str> {
+    args.iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.strip_prefix("--vmservice-out-file") {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_uri_in_log() {
+        let log = "\
+Launching lib/main.dart on Linux in debug mode...
+A Dart VM Service on Linux is available at: http://127.0.0.1:40261/abc=/
+The Flutter DevTools debugger and profiler on Linux is available at: http://127.0.0.1:9100?uri=http://127.0.0.1:40261/abc=/
+Performing hot restart...
+A Dart VM Service on Linux is available at: http://127.0.0.1:41733/xyz=/
+";
+        assert_eq!(
+            uri_from_log(log).as_deref(),
+            Some("http://127.0.0.1:41733/xyz=/")
+        );
+
+        let machine = r#"[{"event":"app.debugPort","params":{"port":4021,"wsUri":"ws://127.0.0.1:4021/abc=/ws"}}]"#;
+        assert_eq!(
+            uri_from_log(machine).as_deref(),
+            Some("http://127.0.0.1:4021/abc=/")
+        );
+        assert_eq!(uri_from_log("Syncing files to device Linux..."), None);
+    }
+
+    #[test]
+    fn ws_uri_to_debug_url() {
+        assert_eq!(
+            debug_url("ws://127.0.0.1:4021/abc=/ws"),
+            "http://127.0.0.1:4021/abc=/"
+        );
+        assert_eq!(debug_url("ws://127.0.0.1:4021"), "http://127.0.0.1:4021/");
+        assert_eq!(
+            debug_url("http://127.0.0.1:4021/abc=/"),
+            "http://127.0.0.1:4021/abc=/"
+        );
+    }
+
+    #[test]
+    fn flutter_run_out_file() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        let run = args(
+            "/opt/flutter/bin/cache/dart-sdk/bin/dart /opt/flutter/bin/cache/flutter_tools.snapshot run --vmservice-out-file=.dart_tool/vm.uri",
+        );
+        assert!(is_flutter_run(&run));
+        assert_eq!(out_file(&run), Some(".dart_tool/vm.uri"));
+        assert_eq!(
+            out_file(&args(
+                "flutter_tools.snapshot run --vmservice-out-file vm.uri"
+            )),
+            Some("vm.uri")
+        );
+        assert_eq!(out_file(&args("flutter_tools.snapshot run -d linux")), None);
+        assert!(!is_flutter_run(&args(
+            "/opt/flutter/bin/cache/flutter_tools.snapshot attach"
+        )));
+    }
+}
@@ -1,10 +1,42 @@
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+use crate::attach;
+use crate::config::Config;
+use crate::debug_flags::{self, DebugFlag, FlagValue};
+use crate::details;
+use crate::devices;
+use crate::diff;
+use crate::eval::{self, EvalTarget};
+use crate::gestures::{self, Drag, Target};
+use crate::golden::{self, CompareOptions};
 use crate::isolate;
+use crate::layout::{self, FlexChange, LayoutNode};
+use crate::logs::{self, LogEntry, LogFilter};
+use crate::machine::{self, MachineLogTail};
+use crate::mcp;
+use crate::overrides::Override;
 use crate::process;
-use crate::snapshot::{self, SnapshotOptions};
+use crate::reload::ReloadReport;
+use crate::screenshot::{self, Dimensions, Mode};
+use crate::session::Session;
+use crate::snapshot::{self, SnapshotOptions, WidgetNode};
 use crate::state::State;
+use crate::supervisor;
+use crate::vm_service::StreamId;
+
+/// A failure the command has already printed; the process just exits nonzero.
+#[derive(Debug)]
+pub struct AlreadyReported;
+
+impl std::fmt::Display for AlreadyReported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("command failed")
+    }
+}
+
+impl std::error::Error for AlreadyReported {}
 
 fn resolve_project_dir(project_dir: Option<String>) -> Result<PathBuf> {
     match project_dir {
@@ -13,28 +45,53 @@ fn resolve_project_dir(project_dir: Option<String>) -> Result<PathBuf> {
     }
 }
 
-pub async fn cmd_snapshot(
+/// Connect to the app, starting flutter run unless a --url is given.
+pub async fn connect(
     project_dir: Option<String>,
     url: Option<String>,
+    isolate: Option<String>,
+    device: Option<String>,
+) -> Result<Session> {
+    Session::connect(resolve_project_dir(project_dir)?, url, isolate, device).await
+}
+
+pub async fn cmd_snapshot(
+    session: &mut Session,
     depth: Option<usize>,
     filter: Option<String>,
     compact: bool,
+    save: Option<String>,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
+    let tree = live_tree(session).await?;
+
+    if let Some(path) = save {
+        snapshot::save_tree(Path::new(&path), &tree)?;
+        let widgets: usize = tree.iter().map(WidgetNode::count).sum();
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({ "path": path, "widgets": widgets })
+            );
+        } else {
+            println!("Snapshot saved to {path} ({widgets} widgets)");
+        }
+        return Ok(());
+    }
 
-    let tree = snapshot::get_widget_tree(&mut conn).await?;
     let opts = SnapshotOptions {
         max_depth: depth,
         filter,
         compact,
     };
-    let output = snapshot::format_tree(&tree, &opts);
 
     if json {
-        println!("{}", serde_json::json!({ "tree": output }));
-    } else if output.is_empty() {
+        println!("{}", snapshot::tree_to_json(&tree, &opts));
+        return Ok(());
+    }
+
+    let output = snapshot::format_tree(&tree, &opts);
+    if output.is_empty() {
         println!("(empty widget tree)");
     } else {
         println!("{output}");
@@ -42,69 +99,209 @@ pub async fn cmd_snapshot(
     Ok(())
 }
 
+/// Fetch the app's widget tree, remembering its value IDs for completion.
+async fn live_tree(session: &mut Session) -> Result<Vec<WidgetNode>> {
+    let isolate_id = session.isolate_id().await?;
+    let tree = snapshot::get_widget_tree(&mut session.conn, &isolate_id).await?;
+    session.value_ids = snapshot::value_ids(&tree);
+    Ok(tree)
+}
+
+/// Compare a saved snapshot with another one, or with the live app when
+/// given a session.
+pub async fn cmd_diff(
+    session: Option<&mut Session>,
+    before: &str,
+    after: &str,
+    compact: bool,
+    json: bool,
+) -> Result<()> {
+    let before_tree = snapshot::load_tree(Path::new(before))?;
+    let after_tree = match session {
+        Some(session) => live_tree(session).await?,
+        None => snapshot::load_tree(Path::new(after))?,
+    };
+
+    let opts = SnapshotOptions {
+        max_depth: None,
+        filter: None,
+        compact,
+    };
+    let changes = diff::diff_trees(
+        &snapshot::prune_tree(&before_tree, &opts),
+        &snapshot::prune_tree(&after_tree, &opts),
+    );
+
+    if json {
+        println!("{}", serde_json::json!(changes));
+    } else {
+        println!("{}", diff::format_changes(&changes));
+    }
+    Ok(())
+}
+
 pub async fn cmd_screenshot(
-    project_dir: Option<String>,
-    url: Option<String>,
+    session: &mut Session,
     id: Option<String>,
+    mode: Mode,
     path: &str,
+    dimensions: Dimensions,
+    compare: Option<CompareOptions>,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
-    let isolate_id = isolate::find_flutter_isolate(&mut conn).await?;
-
-    let mut params = serde_json::json!({
-        "isolateId": isolate_id,
-        "width": 1080.0,
-        "height": 1920.0,
-        "maxPixelRatio": 2.0,
-    });
-    if let Some(ref id) = id {
-        params["id"] = serde_json::json!(id);
+    if mode == Mode::Skp && compare.is_some() {
+        bail!("--compare needs a PNG screenshot, not --mode skp");
     }
+    let bytes = take_screenshot(session, mode, id.as_deref(), dimensions).await?;
+    write_file(path, &bytes)?;
 
-    let result = conn
-        .send("ext.flutter.inspector.screenshot", params)
-        .await?;
+    let Some(compare) = compare else {
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({ "path": path, "bytes": bytes.len() })
+            );
+        } else {
+            println!("Screenshot saved to {path} ({} bytes)", bytes.len());
+        }
+        return Ok(());
+    };
 
-    let image_data = result
-        .get("screenshot")
-        .and_then(|s| s.as_str())
-        .context("No screenshot data in response")?;
+    if compare.update {
+        write_file(&compare.golden, &bytes)?;
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({ "path": path, "bytes": bytes.len(), "updated": compare.golden })
+            );
+        } else {
+            println!(
+                "Screenshot saved to {path} and to golden {}",
+                compare.golden
+            );
+        }
+        return Ok(());
+    }
 
-    use base64::Engine;
-    let bytes = base64::engine::general_purpose::STANDARD.decode(image_data)?;
+    let golden_bytes = std::fs::read(&compare.golden).with_context(|| {
+        format!(
+            "Failed to read golden {} (use --update to create it)",
+            compare.golden
+        )
+    })?;
+    let golden = golden::decode_png(&golden_bytes)
+        .with_context(|| format!("Failed to decode golden {}", compare.golden))?;
+    let actual = golden::decode_png(&bytes)?;
+    let (comparison, diff_image) = golden::compare(&golden, &actual, compare.tolerance);
+    let passed = comparison.passes(compare.threshold);
 
-    // Ensure parent directory exists
-    if let Some(parent) = std::path::Path::new(path).parent() {
-        std::fs::create_dir_all(parent)?;
+    // Only leave a diff image behind when there is something to see
+    let diff_path = (comparison.differing > 0).then(|| {
+        compare
+            .diff_path
+            .clone()
+            .unwrap_or_else(|| golden::default_diff_path(path))
+    });
+    if let Some(ref diff_path) = diff_path {
+        write_file(diff_path, &golden::encode_png(&diff_image)?)?;
     }
-    std::fs::write(path, &bytes)?;
 
     if json {
         println!(
             "{}",
-            serde_json::json!({ "path": path, "bytes": bytes.len() })
+            serde_json::json!({
+                "path": path,
+                "bytes": bytes.len(),
+                "golden": compare.golden,
+                "passed": passed,
+                "percent": comparison.percent(),
+                "diff": diff_path,
+                "comparison": comparison,
+            })
         );
     } else {
         println!("Screenshot saved to {path} ({} bytes)", bytes.len());
+        let verdict = if passed { "Matches" } else { "Differs from" };
+        print!(
+            "{verdict} {}: {} of {} pixels ({:.2}%) differ",
+            compare.golden,
+            comparison.differing,
+            comparison.total,
+            comparison.percent()
+        );
+        if comparison.golden_size != comparison.actual_size {
+            let (gw, gh) = comparison.golden_size;
+            let (aw, ah) = comparison.actual_size;
+            print!(", size {gw}x{gh} vs {aw}x{ah}");
+        }
+        println!();
+        if let Some(diff_path) = diff_path {
+            println!("Diff written to {diff_path}");
+        }
+    }
+    if !passed {
+        return Err(AlreadyReported.into());
     }
     Ok(())
 }
 
+/// Write a file, creating its parent directory.
+fn write_file(path: &str, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, bytes).with_context(|| format!("Failed to write {path}"))
+}
+
+/// Capture a screenshot: PNG bytes, or an SKP picture in [`Mode::Skp`].
+/// Unset dimensions come from `.flutter-cli.toml`, then from the app itself.
+pub async fn take_screenshot(
+    session: &mut Session,
+    mode: Mode,
+    id: Option<&str>,
+    dimensions: Dimensions,
+) -> Result<Vec<u8>> {
+    if mode != Mode::Widget && id.is_some() {
+        bail!("--id only applies to widget screenshots");
+    }
+    if mode != Mode::Widget && (dimensions.width.is_some() || dimensions.height.is_some()) {
+        bail!("--width and --height only apply to widget screenshots");
+    }
+    let config = Config::load(&session.project_dir)?;
+    let dimensions = dimensions.or(config.screenshot);
+
+    let isolate_id = session.isolate_id().await?;
+    match mode {
+        Mode::Widget => screenshot::capture(&mut session.conn, &isolate_id, id, dimensions).await,
+        Mode::Window => {
+            screenshot::capture_window(&mut session.conn, &isolate_id, dimensions.pixel_ratio).await
+        }
+        Mode::Skp => screenshot::capture_skp(&mut session.conn).await,
+    }
+}
+
 pub async fn cmd_details(
-    project_dir: Option<String>,
-    url: Option<String>,
+    session: &mut Session,
     value_id: &str,
     depth: usize,
+    verbose: bool,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
-    let isolate_id = isolate::find_flutter_isolate(&mut conn).await?;
+    let result = fetch_details(session, value_id, depth).await?;
+    print_inspector_result(&result, verbose, json)
+}
+
+/// The inspector's details subtree for a value ID.
+pub async fn fetch_details(
+    session: &mut Session,
+    value_id: &str,
+    depth: usize,
+) -> Result<serde_json::Value> {
+    let isolate_id = session.isolate_id().await?;
     let object_group = "flutter-cli-details";
 
-    let result = conn
+    let result = session
+        .conn
         .send(
             "ext.flutter.inspector.getDetailsSubtree",
             serde_json::json!({
@@ -116,37 +313,32 @@ pub async fn cmd_details(
         )
         .await?;
 
-    // Cleanup
-    let _ = conn
-        .send(
-            "ext.flutter.inspector.disposeGroup",
-            serde_json::json!({
-                "isolateId": isolate_id,
-                "objectGroup": object_group,
-            }),
-        )
-        .await;
+    dispose_group(session, &isolate_id, object_group).await;
+    Ok(result)
+}
 
+pub async fn cmd_layout(
+    session: &mut Session,
+    value_id: &str,
+    verbose: bool,
+    json: bool,
+) -> Result<()> {
+    let result = fetch_layout(session, value_id).await?;
     if json {
         println!("{}", serde_json::to_string(&result)?);
     } else {
-        println!("{}", serde_json::to_string_pretty(&result)?);
+        println!("{}", layout::format_layout(&result, verbose));
     }
     Ok(())
 }
 
-pub async fn cmd_layout(
-    project_dir: Option<String>,
-    url: Option<String>,
-    value_id: &str,
-    json: bool,
-) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
-    let isolate_id = isolate::find_flutter_isolate(&mut conn).await?;
+/// The inspector's layout explorer node for a value ID.
+pub async fn fetch_layout(session: &mut Session, value_id: &str) -> Result<serde_json::Value> {
+    let isolate_id = session.isolate_id().await?;
     let object_group = "flutter-cli-layout";
 
-    let result = conn
+    let result = session
+        .conn
         .send(
             "ext.flutter.inspector.getLayoutExplorerNode",
             serde_json::json!({
@@ -158,7 +350,74 @@ pub async fn cmd_layout(
         )
         .await?;
 
-    let _ = conn
+    dispose_group(session, &isolate_id, object_group).await;
+    Ok(result)
+}
+
+/// Try out flex changes on a widget through the inspector, then show its new
+/// layout.
+pub async fn cmd_set_flex(
+    session: &mut Session,
+    value_id: &str,
+    change: &FlexChange,
+    verbose: bool,
+    json: bool,
+) -> Result<()> {
+    let isolate_id = session.isolate_id().await?;
+
+    if change.main_axis.is_some() || change.cross_axis.is_some() {
+        let current = LayoutNode::parse(&fetch_layout(session, value_id).await?).flex;
+        let (main, cross) = change
+            .alignment_args(current.as_ref())
+            .with_context(|| format!("{value_id} is not a Row, Column or Flex"))?;
+        let params = serde_json::json!({
+            "id": value_id,
+            "mainAxisAlignment": main,
+            "crossAxisAlignment": cross,
+        });
+        if !set_flex_property(session, &isolate_id, "setFlexProperties", params).await? {
+            bail!("{value_id} is not a Row, Column or Flex");
+        }
+    }
+    if let Some(factor) = change.factor {
+        // Flutter treats a flex factor of 0 like none: the child is not flexible
+        let params = serde_json::json!({ "id": value_id, "flexFactor": factor.to_string() });
+        if !set_flex_property(session, &isolate_id, "setFlexFactor", params).await? {
+            bail!("{value_id} is not a child of a Row, Column or Flex");
+        }
+    }
+    if let Some(fit) = change.fit_arg() {
+        let params = serde_json::json!({ "id": value_id, "flexFit": fit });
+        if !set_flex_property(session, &isolate_id, "setFlexFit", params).await? {
+            bail!("{value_id} is not a child of a Row, Column or Flex");
+        }
+    }
+
+    // The change is laid out on the next frame
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    cmd_layout(session, value_id, verbose, json).await
+}
+
+/// Call one of the inspector's flex setters, which report whether the widget
+/// had the flex (parent data) to change.
+async fn set_flex_property(
+    session: &mut Session,
+    isolate_id: &str,
+    setter: &str,
+    mut params: serde_json::Value,
+) -> Result<bool> {
+    params["isolateId"] = serde_json::json!(isolate_id);
+    let result = session
+        .conn
+        .send(&format!("ext.flutter.inspector.{setter}"), params)
+        .await?;
+    Ok(result.get("result").and_then(|r| r.as_bool()) == Some(true))
+}
+
+/// Release the inspector objects a command kept alive.
+async fn dispose_group(session: &mut Session, isolate_id: &str, object_group: &str) {
+    let _ = session
+        .conn
         .send(
             "ext.flutter.inspector.disposeGroup",
             serde_json::json!({
@@ -167,32 +426,96 @@ pub async fn cmd_layout(
             }),
         )
         .await;
+}
 
+/// Print an inspector `DiagnosticsNode` as a properties tree, or raw with --json.
+fn print_inspector_result(result: &serde_json::Value, verbose: bool, json: bool) -> Result<()> {
     if json {
-        println!("{}", serde_json::to_string(&result)?);
+        println!("{}", serde_json::to_string(result)?);
     } else {
-        println!("{}", serde_json::to_string_pretty(&result)?);
+        println!("{}", details::format_details(result, verbose));
     }
     Ok(())
 }
 
-pub async fn cmd_dump_render(
-    project_dir: Option<String>,
-    url: Option<String>,
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_eval(
+    session: &mut Session,
+    expression: &str,
+    library: Option<String>,
+    value_id: Option<String>,
+    state: bool,
+    frame: Option<u32>,
+    depth: usize,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
-    let isolate_id = isolate::find_flutter_isolate(&mut conn).await?;
+    let isolate_id = session.isolate_id().await?;
 
-    let result = conn
-        .send(
-            "ext.flutter.debugDumpRenderTree",
-            serde_json::json!({ "isolateId": isolate_id }),
-        )
-        .await?;
+    let target = match (library, value_id, frame) {
+        (Some(uri), _, _) => EvalTarget::Library(uri),
+        (_, Some(value_id), _) => EvalTarget::Object { value_id, state },
+        (_, _, Some(index)) => EvalTarget::Frame(index),
+        _ => EvalTarget::RootLibrary,
+    };
+    let instance =
+        eval::evaluate_target(&mut session.conn, &isolate_id, &target, expression).await?;
+    let value = eval::expand(&mut session.conn, &isolate_id, &instance, depth).await?;
 
-    let text = result.get("data").and_then(|d| d.as_str()).unwrap_or("");
+    if json {
+        println!("{}", serde_json::to_string(&value)?);
+    } else {
+        println!("{}", eval::format_value(&value));
+    }
+    Ok(())
+}
+
+pub async fn cmd_isolates(session: &mut Session, json: bool) -> Result<()> {
+    let isolates = isolate::list_isolates(&mut session.conn).await?;
+    // Listing still helps when the current selection matches nothing
+    let selected = session.isolate_id().await.ok();
+
+    if json {
+        let isolates: Vec<_> = isolates
+            .iter()
+            .map(|info| {
+                serde_json::json!({
+                    "id": info.id,
+                    "name": info.name,
+                    "number": info.number,
+                    "flutter": info.is_flutter(),
+                    "selected": selected.as_deref() == Some(info.id.as_str()),
+                    "extensions": info.extensions,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::json!(isolates));
+        return Ok(());
+    }
+
+    for info in &isolates {
+        let marker = if selected.as_deref() == Some(info.id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        let kind = if info.is_flutter() { "  flutter" } else { "" };
+        println!(
+            "{marker} {}  {}  #{}  {} extensions{kind}",
+            info.id,
+            info.name,
+            info.number,
+            info.extensions.len()
+        );
+    }
+    match session.isolate_selector() {
+        Some(selector) => println!("Selected with --isolate {selector}"),
+        None => println!("Selected automatically (first isolate with ext.flutter extensions)"),
+    }
+    Ok(())
+}
+
+pub async fn cmd_dump_render(session: &mut Session, json: bool) -> Result<()> {
+    let text = dump_render(session).await?;
 
     if json {
         println!("{}", serde_json::json!({ "render_tree": text }));
@@ -202,120 +525,424 @@ pub async fn cmd_dump_render(
     Ok(())
 }
 
-pub async fn cmd_dump_semantics(
-    project_dir: Option<String>,
-    url: Option<String>,
+/// The render tree as the framework's text dump.
+pub async fn dump_render(session: &mut Session) -> Result<String> {
+    dump_tree(session, "ext.flutter.debugDumpRenderTree").await
+}
+
+pub async fn cmd_dump_semantics(session: &mut Session, json: bool) -> Result<()> {
+    let text = dump_semantics(session).await?;
+
+    if json {
+        println!("{}", serde_json::json!({ "semantics_tree": text }));
+    } else {
+        println!("{text}");
+    }
+    Ok(())
+}
+
+/// The semantics tree in traversal order as the framework's text dump.
+pub async fn dump_semantics(session: &mut Session) -> Result<String> {
+    dump_tree(
+        session,
+        "ext.flutter.debugDumpSemanticsTreeInTraversalOrder",
+    )
+    .await
+}
+
+async fn dump_tree(session: &mut Session, method: &str) -> Result<String> {
+    let isolate_id = session.isolate_id().await?;
+
+    let result = session
+        .conn
+        .send(method, serde_json::json!({ "isolateId": isolate_id }))
+        .await?;
+
+    Ok(result
+        .get("data")
+        .and_then(|d| d.as_str())
+        .unwrap_or("")
+        .to_string())
+}
+
+/// Print debug settings: the given one, or all of them.
+pub async fn cmd_debug_get(
+    session: &mut Session,
+    flag: Option<DebugFlag>,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
-    let isolate_id = isolate::find_flutter_isolate(&mut conn).await?;
+    let isolate_id = session.isolate_id().await?;
+    let Some(flag) = flag else {
+        let mut values = serde_json::Map::new();
+        for &flag in DebugFlag::all() {
+            // Most settings only exist in debug builds
+            let value = debug_flags::get(&mut session.conn, &isolate_id, flag)
+                .await
+                .ok();
+            if !json {
+                let value = value.map_or("unavailable".to_string(), |v| v.to_string());
+                println!("{:<32} {value}", flag.name());
+            }
+            values.insert(flag.name(), serde_json::json!(value));
+        }
+        if json {
+            println!("{}", serde_json::Value::Object(values));
+        }
+        return Ok(());
+    };
+    let value = debug_flags::get(&mut session.conn, &isolate_id, flag).await?;
+    print_flag(flag, value, json);
+    Ok(())
+}
 
-    let result = conn
-        .send(
-            "ext.flutter.debugDumpSemanticsTreeInTraversalOrder",
-            serde_json::json!({ "isolateId": isolate_id }),
-        )
-        .await?;
+/// Set a debug setting, or toggle it when no value is given. With
+/// `temporary` the original value is restored when the session ends.
+pub async fn cmd_debug_set(
+    session: &mut Session,
+    flag: DebugFlag,
+    value: Option<&str>,
+    temporary: bool,
+    json: bool,
+) -> Result<()> {
+    let isolate_id = session.isolate_id().await?;
+    let value = value.map(|v| flag.parse_value(v)).transpose()?;
+    let current = debug_flags::get(&mut session.conn, &isolate_id, flag).await?;
+    let value = debug_flags::set(
+        &mut session.conn,
+        &isolate_id,
+        flag,
+        value.unwrap_or_else(|| current.toggled()),
+    )
+    .await?;
 
-    let text = result.get("data").and_then(|d| d.as_str()).unwrap_or("");
+    let pending = session.temporary_flags.iter().position(|(f, _)| *f == flag);
+    match (temporary, pending) {
+        (true, None) => session.temporary_flags.push((flag, current)),
+        // The value set without --temporary is the one to keep
+        (false, Some(i)) => {
+            session.temporary_flags.remove(i);
+        }
+        _ => {}
+    }
+    print_flag(flag, value, json);
+    Ok(())
+}
+
+/// Restore the debug settings changed with --temporary.
+pub async fn restore_debug_flags(session: &mut Session) {
+    let Ok(isolate_id) = session.isolate_id().await else {
+        eprintln!("Could not restore debug settings: the app is gone");
+        return;
+    };
+    for (flag, value) in std::mem::take(&mut session.temporary_flags) {
+        match debug_flags::set(&mut session.conn, &isolate_id, flag, value).await {
+            Ok(value) => eprintln!("Restored {} to {value}", flag.name()),
+            Err(e) => eprintln!("Could not restore {}: {e:#}", flag.name()),
+        }
+    }
+}
 
+fn print_flag(flag: DebugFlag, value: FlagValue, json: bool) {
     if json {
-        println!("{}", serde_json::json!({ "semantics_tree": text }));
+        println!("{}", serde_json::json!({ flag.name(): value }));
     } else {
-        println!("{text}");
+        println!("{}: {value}", flag.name());
+    }
+}
+
+/// Apply a platform, brightness, text scale or locale override, or show the
+/// current value when none is given.
+pub async fn cmd_override(session: &mut Session, setting: &Override, json: bool) -> Result<()> {
+    let isolate_id = session.isolate_id().await?;
+    let value = setting.apply(&mut session.conn, &isolate_id).await?;
+    if json {
+        println!("{}", serde_json::json!({ setting.name(): value }));
+    } else {
+        println!("{}: {value}", setting.name());
     }
     Ok(())
 }
 
-pub async fn cmd_reload(
-    project_dir: Option<String>,
-    url: Option<String>,
+pub async fn cmd_tap(session: &mut Session, target: &str, json: bool) -> Result<()> {
+    let isolate_id = session.isolate_id().await?;
+    let target = Target::parse(target);
+    let hit = gestures::tap(&mut session.conn, &isolate_id, &target).await?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "action": "tap",
+                "widget": hit.widget,
+                "x": hit.x,
+                "y": hit.y,
+                "rect": hit.rect,
+            })
+        );
+    } else {
+        println!("Tapped {} at ({:.1}, {:.1})", hit.widget, hit.x, hit.y);
+    }
+    Ok(())
+}
+
+pub async fn cmd_enter_text(
+    session: &mut Session,
+    target: Option<String>,
+    text: &str,
+    append: bool,
+    submit: bool,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
+    let isolate_id = session.isolate_id().await?;
+    let target = target.as_deref().map(Target::parse);
+    let entry = gestures::enter_text(
+        &mut session.conn,
+        &isolate_id,
+        target.as_ref(),
+        text,
+        append,
+        submit,
+    )
+    .await?;
 
-    // Hot reload via flutter run --machine stdin protocol
-    if url.is_none()
-        && let Some(state) = State::load(&project_dir)?
-        && state.is_pid_alive()
-    {
-        return send_machine_command(&state, false, json);
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "action": "enter_text",
+                "widget": entry.widget,
+                "text": entry.text,
+                "submitted": entry.submitted,
+            })
+        );
+    } else {
+        let verb = if append { "Appended" } else { "Entered" };
+        let submitted = if entry.submitted {
+            " and submitted"
+        } else {
+            ""
+        };
+        println!(
+            "{verb} {:?} into {}{submitted} (now {:?})",
+            text, entry.widget, entry.text
+        );
     }
+    Ok(())
+}
 
-    // Fallback: use VM Service directly
-    let mut conn = process::ensure_connection(&project_dir, url.as_deref()).await?;
-    let isolate_id = isolate::find_flutter_isolate(&mut conn).await?;
+pub async fn cmd_scroll(
+    session: &mut Session,
+    target: &str,
+    dx: f64,
+    dy: f64,
+    json: bool,
+) -> Result<()> {
+    let isolate_id = session.isolate_id().await?;
+    let target = Target::parse(target);
+    let scroll = gestures::scroll(&mut session.conn, &isolate_id, &target, dx, dy).await?;
 
-    let result = conn
-        .send(
-            "ext.flutter.reassemble",
-            serde_json::json!({ "isolateId": isolate_id }),
-        )
-        .await?;
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "action": "scroll",
+                "widget": scroll.at.widget,
+                "x": scroll.at.x,
+                "y": scroll.at.y,
+                "dx": dx,
+                "dy": dy,
+                "from": scroll.from,
+                "to": scroll.to,
+            })
+        );
+    } else {
+        print!("Scrolled {} by ({dx:.1}, {dy:.1})", scroll.at.widget);
+        match (scroll.from, scroll.to) {
+            (Some(from), Some(to)) => println!(": offset {from:.1} -> {to:.1}"),
+            _ => println!(),
+        }
+    }
+    Ok(())
+}
+
+pub async fn cmd_scroll_until_visible(
+    session: &mut Session,
+    target: &str,
+    find: &str,
+    dx: f64,
+    dy: f64,
+    max_scrolls: u32,
+    json: bool,
+) -> Result<()> {
+    let isolate_id = session.isolate_id().await?;
+    let target = Target::parse(target);
+    let find = Target::parse(find);
+    let (hit, scrolls) = gestures::scroll_until_visible(
+        &mut session.conn,
+        &isolate_id,
+        &target,
+        &find,
+        dx,
+        dy,
+        max_scrolls,
+    )
+    .await?;
 
     if json {
-        println!("{}", serde_json::to_string(&result)?);
+        println!(
+            "{}",
+            serde_json::json!({
+                "action": "scroll_until_visible",
+                "widget": hit.widget,
+                "x": hit.x,
+                "y": hit.y,
+                "rect": hit.rect,
+                "scrolls": scrolls,
+            })
+        );
     } else {
-        println!("Hot reload triggered");
+        println!(
+            "Found {} at ({:.1}, {:.1}) after {scrolls} scroll(s)",
+            hit.widget, hit.x, hit.y
+        );
     }
     Ok(())
 }
 
-pub async fn cmd_restart(
-    project_dir: Option<String>,
-    url: Option<String>,
+pub async fn cmd_drag(
+    session: &mut Session,
+    target: &str,
+    dx: f64,
+    dy: f64,
+    duration_ms: u64,
     json: bool,
 ) -> Result<()> {
-    let project_dir = resolve_project_dir(project_dir)?;
+    let isolate_id = session.isolate_id().await?;
+    let target = Target::parse(target);
+    let drag = Drag {
+        dx,
+        dy,
+        duration: std::time::Duration::from_millis(duration_ms),
+        precise: false,
+    };
+    let hit = gestures::drag(&mut session.conn, &isolate_id, &target, &drag).await?;
 
-    // Hot restart via flutter run --machine stdin protocol
-    if url.is_none()
-        && let Some(state) = State::load(&project_dir)?
-        && state.is_pid_alive()
-    {
-        return send_machine_command(&state, true, json);
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "action": "drag",
+                "widget": hit.widget,
+                "x": hit.x,
+                "y": hit.y,
+                "dx": dx,
+                "dy": dy,
+                "duration_ms": duration_ms,
+            })
+        );
+    } else {
+        println!(
+            "Dragged {} from ({:.1}, {:.1}) by ({dx:.1}, {dy:.1}) over {duration_ms}ms",
+            hit.widget, hit.x, hit.y
+        );
     }
+    Ok(())
+}
 
-    // Fallback: VM Service doesn't have a clean hot restart method
-    // without the flutter tool, so we need the managed process
-    anyhow::bail!("Hot restart requires a managed flutter run process. Run without --url first.");
+pub async fn cmd_reload(session: &mut Session, json: bool) -> Result<()> {
+    print_reload_report(&hot_reload(session).await?, json)
 }
 
-fn send_machine_command(state: &State, full_restart: bool, json: bool) -> Result<()> {
-    use std::io::Write;
+/// Hot reload the app and report how it went.
+pub async fn hot_reload(session: &mut Session) -> Result<ReloadReport> {
+    // Hot reload through the supervisor owning flutter run --machine
+    if let Some(state) = session.managed_state()? {
+        return send_machine_command(&session.project_dir, &state, false).await;
+    }
 
-    let app_id = state.app_id.as_deref().unwrap_or("");
-    let cmd = serde_json::json!([{
-        "method": "app.restart",
-        "params": {
-            "appId": app_id,
-            "fullRestart": full_restart,
-            "reason": "flutter-cli",
-        }
-    }]);
+    // Fallback: have the VM recompile and reload the sources itself, then
+    // rebuild the widget tree if it took the new code
+    let isolate_id = session.isolate_id().await?;
 
-    // Write to the flutter run process stdin via /proc/PID/fd/0
-    let stdin_path = format!("/proc/{}/fd/0", state.pid);
-    let mut file = std::fs::OpenOptions::new()
-        .write(true)
-        .open(&stdin_path)
-        .context("Failed to write to flutter run stdin")?;
-    writeln!(file, "{}", cmd)?;
+    let start = Instant::now();
+    let result = session
+        .conn
+        .send(
+            "reloadSources",
+            serde_json::json!({ "isolateId": isolate_id }),
+        )
+        .await?;
+    let report = ReloadReport::from_reload_sources("Hot reload", &result, start.elapsed());
+    if !report.succeeded() {
+        return Ok(report);
+    }
+    session
+        .conn
+        .send(
+            "ext.flutter.reassemble",
+            serde_json::json!({ "isolateId": isolate_id }),
+        )
+        .await?;
+    Ok(ReloadReport {
+        elapsed_ms: start.elapsed().as_millis(),
+        ..report
+    })
+}
+
+pub async fn cmd_restart(session: &mut Session, json: bool) -> Result<()> {
+    print_reload_report(&hot_restart(session).await?, json)
+}
+
+/// Hot restart the app and report how it went.
+pub async fn hot_restart(session: &mut Session) -> Result<ReloadReport> {
+    // Hot restart through the supervisor owning flutter run --machine
+    if let Some(state) = session.managed_state()? {
+        // The restarted app runs in a new isolate
+        session.forget_isolate()?;
+        return send_machine_command(&session.project_dir, &state, true).await;
+    }
+
+    // Fallback: VM Service doesn't have a clean hot restart method
+    // without the flutter tool, so we need the managed process
+    anyhow::bail!("Hot restart requires a managed flutter run process. Run without --url first.");
+}
 
+async fn send_machine_command(
+    project_dir: &Path,
+    state: &State,
+    full_restart: bool,
+) -> Result<ReloadReport> {
     let action = if full_restart {
         "Hot restart"
     } else {
         "Hot reload"
     };
+    let app_id = state.app_id.as_deref().unwrap_or("");
+
+    let start = Instant::now();
+    let result = supervisor::request(
+        project_dir,
+        "app.restart",
+        serde_json::json!({
+            "appId": app_id,
+            "fullRestart": full_restart,
+            "reason": "flutter-cli",
+        }),
+    )
+    .await?;
+
+    Ok(ReloadReport::from_result(action, &result, start.elapsed()))
+}
 
+/// Print the outcome of a reload or restart, failing with [`AlreadyReported`] if it failed.
+fn print_reload_report(report: &ReloadReport, json: bool) -> Result<()> {
     if json {
-        println!(
-            "{}",
-            serde_json::json!({ "action": action, "status": "sent" })
-        );
+        println!("{}", serde_json::json!(report));
     } else {
-        println!("{action} triggered");
+        println!("{}", report.format());
+    }
+    if !report.succeeded() {
+        return Err(AlreadyReported.into());
     }
     Ok(())
 }
@@ -332,7 +959,7 @@ pub async fn cmd_status(
     }
 
     match State::load(&project_dir)? {
-        Some(state) => print_managed_status(&state, json).await,
+        Some(state) => print_managed_status(&project_dir, &state, json).await,
         None => {
             if json {
                 println!(
@@ -362,7 +989,7 @@ async fn print_url_status(url: &str, json: bool) -> Result<()> {
     Ok(())
 }
 
-async fn print_managed_status(state: &State, json: bool) -> Result<()> {
+async fn print_managed_status(project_dir: &Path, state: &State, json: bool) -> Result<()> {
     let pid_alive = state.is_pid_alive();
     let ws_reachable = if pid_alive {
         match crate::vm_service::try_connect(&state.ws_uri, 2000).await {
@@ -372,6 +999,17 @@ async fn print_managed_status(state: &State, json: bool) -> Result<()> {
     } else {
         false
     };
+    let supervisor_status = if pid_alive {
+        supervisor::request(project_dir, "supervisor.status", serde_json::json!({}))
+            .await
+            .ok()
+    } else {
+        None
+    };
+    let progress = supervisor_status
+        .as_ref()
+        .and_then(|s| s.get("progress"))
+        .and_then(|p| p.as_str());
 
     if json {
         println!(
@@ -380,8 +1018,12 @@ async fn print_managed_status(state: &State, json: bool) -> Result<()> {
                 "pid": state.pid,
                 "ws_uri": state.ws_uri,
                 "app_id": state.app_id,
+                "device": state.device,
                 "pid_alive": pid_alive,
                 "ws_reachable": ws_reachable,
+                "supervisor_pid": state.supervisor_pid,
+                "supervisor_reachable": supervisor_status.is_some(),
+                "progress": progress,
                 "managed": true,
             })
         );
@@ -395,12 +1037,250 @@ async fn print_managed_status(state: &State, json: bool) -> Result<()> {
         if let Some(ref id) = state.app_id {
             println!("App ID: {id}");
         }
+        if let Some(ref device) = state.device {
+            println!("Device: {device}");
+        }
         println!("Reachable: {ws_reachable}");
+        if let Some(pid) = state.supervisor_pid {
+            let reachable = if supervisor_status.is_some() {
+                "reachable"
+            } else {
+                "unreachable"
+            };
+            println!("Supervisor: PID {pid} ({reachable})");
+        }
+        if let Some(progress) = progress {
+            println!("In progress: {progress}");
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_logs(
+    project_dir: Option<String>,
+    url: Option<String>,
+    device: Option<String>,
+    follow: bool,
+    since: Option<String>,
+    level: Option<String>,
+    logger: Option<String>,
+    json: bool,
+) -> Result<()> {
+    use futures::StreamExt;
+
+    let project_dir = resolve_project_dir(project_dir)?;
+    let filter = LogFilter {
+        min_level: level.as_deref().map(logs::parse_level).transpose()?,
+        logger,
+        since: since
+            .as_deref()
+            .map(|s| logs::parse_since(s, machine::now_millis()))
+            .transpose()?,
+    };
+
+    // Connect first: this may start flutter run, which creates the machine log
+    let conn = if follow {
+        Some(process::ensure_connection(&project_dir, url.as_deref(), device.as_deref()).await?)
+    } else {
+        None
+    };
+
+    // app.log events recorded from the managed flutter run's stdout
+    let mut tail = if url.is_none() && State::load(&project_dir)?.is_some() {
+        MachineLogTail::open(&process::machine_log_path(&project_dir)).ok()
+    } else {
+        None
+    };
+    if let Some(ref mut tail) = tail {
+        print_machine_logs(tail, &filter, json);
+    }
+
+    let Some(mut conn) = conn else {
+        if tail.is_none() {
+            eprintln!("No recorded output. Use --follow to stream from the VM Service.");
+        }
+        return Ok(());
+    };
+
+    let mut streams = vec![conn.stream_listen(StreamId::Logging).await?];
+    // A managed flutter run already reports print() output as app.log
+    if tail.is_none() {
+        streams.push(conn.stream_listen(StreamId::Stdout).await?);
+        streams.push(conn.stream_listen(StreamId::Stderr).await?);
+    }
+    let mut events = futures::stream::select_all(streams);
+    let mut poll = tokio::time::interval(std::time::Duration::from_millis(250));
+
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                for entry in logs::entries_from_event(&event) {
+                    print_log(&entry, &filter, json);
+                }
+            }
+            _ = poll.tick() => {
+                if let Some(ref mut tail) = tail {
+                    print_machine_logs(tail, &filter, json);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    Ok(())
+}
+
+fn print_machine_logs(tail: &mut MachineLogTail, filter: &LogFilter, json: bool) {
+    for entry in tail.read_new() {
+        if let Some(log) = logs::entry_from_machine(&entry) {
+            print_log(&log, filter, json);
+        }
+    }
+}
+
+fn print_log(entry: &LogEntry, filter: &LogFilter, json: bool) {
+    if !filter.matches(entry) {
+        return;
+    }
+    if json {
+        println!("{}", serde_json::json!(entry));
+    } else {
+        println!("{}", logs::format_entry(entry));
+    }
+}
+
+pub async fn cmd_attach(
+    project_dir: Option<String>,
+    device: Option<String>,
+    uri: Option<String>,
+    log: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let project_dir = resolve_project_dir(project_dir)?;
+    let (debug_url, source) =
+        attach::discover(&project_dir, uri.as_deref(), log.as_deref().map(Path::new))?;
+    let ws_uri = process::attach(&project_dir, device.as_deref(), debug_url.as_deref()).await?;
+    let pid = State::load(&project_dir)?.map(|s| s.pid);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "ws_uri": ws_uri, "pid": pid, "source": source })
+        );
+    } else {
+        println!("Attached to {ws_uri} (found via {})", source.describe());
+        if let Some(pid) = pid {
+            println!("flutter attach PID: {pid}");
+        }
+    }
+    Ok(())
+}
+
+pub async fn cmd_devices(
+    project_dir: Option<String>,
+    device: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let project_dir = resolve_project_dir(project_dir)?;
+    let wanted = Config::load(&project_dir)?.with_device(device).device;
+    let wanted = wanted.filter(|w| w != "auto");
+    let devices = devices::list_devices().await?;
+    let selected = wanted
+        .as_deref()
+        .and_then(|w| devices::pick(&devices, Some(w)))
+        .map(|d| d.id.clone());
+
+    if json {
+        let devices: Vec<_> = devices
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "id": d.id,
+                    "name": d.name,
+                    "platform": d.platform,
+                    "emulator": d.emulator,
+                    "selected": selected.as_deref() == Some(d.id.as_str()),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::json!(devices));
+        return Ok(());
+    }
+
+    if devices.is_empty() {
+        println!("No devices found");
+    }
+    for d in &devices {
+        let marker = if selected.as_deref() == Some(d.id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        let kind = if d.emulator { "  emulator" } else { "" };
+        println!("{marker} {}  {}  {}{kind}", d.id, d.name, d.platform);
+    }
+    match (wanted, selected) {
+        (Some(_), Some(id)) => println!("The app starts on {id}"),
+        (Some(wanted), None) => println!("Device {wanted} is not connected"),
+        (None, _) => println!("No device configured; flutter picks one"),
+    }
+    Ok(())
+}
+
+pub async fn cmd_emulators(launch: Option<String>, cold: bool, json: bool) -> Result<()> {
+    if let Some(id) = launch {
+        devices::launch_emulator(&id, cold).await?;
+        if json {
+            println!("{}", serde_json::json!({ "launched": id }));
+        } else {
+            println!("Launched {id}");
+        }
+        return Ok(());
+    }
+
+    let emulators = devices::list_emulators().await?;
+    if json {
+        println!("{}", serde_json::json!(emulators));
+        return Ok(());
+    }
+    if emulators.is_empty() {
+        println!("No emulators available");
+    }
+    for emulator in &emulators {
+        println!(
+            "{}  {}  {}",
+            emulator.id,
+            emulator.name,
+            emulator.platform_type.as_deref().unwrap_or("")
+        );
     }
     Ok(())
 }
 
 pub async fn cmd_stop(project_dir: Option<String>) -> Result<()> {
     let project_dir = resolve_project_dir(project_dir)?;
-    process::stop_process(&project_dir)
+    process::stop_process(&project_dir).await
+}
+
+pub async fn cmd_mcp(
+    project_dir: Option<String>,
+    url: Option<String>,
+    isolate: Option<String>,
+    device: Option<String>,
+) -> Result<()> {
+    let project_dir = resolve_project_dir(project_dir)?;
+    mcp::run(project_dir, url, isolate, device).await
+}
+
+pub async fn cmd_supervise(
+    project_dir: Option<String>,
+    device: Option<String>,
+    attach: bool,
+    debug_url: Option<String>,
+) -> Result<()> {
+    let project_dir = resolve_project_dir(project_dir)?;
+    supervisor::run(&project_dir, device, attach, debug_url.as_deref()).await
 }
@@ -1,7 +1,9 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use serde::Deserialize;
 use std::path::Path;
 
+use crate::screenshot::Dimensions;
+
 const CONFIG_FILENAME: &str = ".flutter-cli.toml";
 
 #[derive(Deserialize, Debug, Default)]
@@ -16,6 +18,12 @@ pub struct Config {
     pub dart_define_from_file: Option<String>,
     #[serde(default)]
     pub extra_args: Vec<String>,
+    /// Manage the app through one `flutter daemon` instead of `flutter run`.
+    #[serde(default)]
+    pub daemon: bool,
+    /// Default `screenshot` size and pixel ratio (`[screenshot]` table).
+    #[serde(default)]
+    pub screenshot: Dimensions,
 }
 
 impl Config {
@@ -31,6 +39,14 @@ impl Config {
         Ok(config)
     }
 
+    /// Apply a `--device` given for one invocation over the configured one.
+    pub fn with_device(mut self, device: Option<String>) -> Self {
+        if device.is_some() {
+            self.device = device;
+        }
+        self
+    }
+
     /// Build the arguments for `flutter run --machine`.
     pub fn flutter_run_args(&self) -> Vec<String> {
         let mut args = vec!["run".to_string(), "--machine".to_string()];
@@ -60,4 +76,117 @@ impl Config {
 
         args
     }
+
+    /// Build the `app.start` parameters for `flutter daemon`, without the
+    /// `deviceId` (picked from the daemon's devices). `extra_args` are mapped
+    /// where `app.start` has an equivalent and rejected otherwise.
+    pub fn app_start_params(&self, project_dir: &Path) -> Result<serde_json::Value> {
+        let mut params = serde_json::json!({
+            "projectDirectory": project_dir,
+            "mode": "debug",
+            "hot": true,
+        });
+
+        if let Some(ref flavor) = self.flavor {
+            params["flavor"] = serde_json::json!(flavor);
+        }
+
+        if let Some(ref target) = self.target {
+            params["target"] = serde_json::json!(target);
+        }
+
+        if self.dart_define_from_file.is_some() {
+            bail!("dart_define_from_file is not supported with daemon = true");
+        }
+
+        let mut extra = self.extra_args.iter();
+        while let Some(arg) = extra.next() {
+            match arg.as_str() {
+                "--debug" | "--profile" | "--release" => {
+                    params["mode"] = serde_json::json!(&arg[2..]);
+                }
+                "--start-paused" => params["startPaused"] = serde_json::json!(true),
+                "--no-hot" => params["hot"] = serde_json::json!(false),
+                "--route" => match extra.next() {
+                    Some(route) => params["route"] = serde_json::json!(route),
+                    None => bail!("--route in extra_args needs a value"),
+                },
+                _ => match arg.strip_prefix("--route=") {
+                    Some(route) => params["route"] = serde_json::json!(route),
+                    None => bail!(
+                        "extra_args entry {arg} has no app.start equivalent with daemon = true"
+                    ),
+                },
+            }
+        }
+
+        Ok(params)
+    }
+
+    /// Build the arguments for `flutter attach --machine`, connecting to
+    /// `debug_url` when given and otherwise letting flutter find the app.
+    /// `extra_args` are left out since they are meant for `flutter run`.
+    pub fn flutter_attach_args(&self, debug_url: Option<&str>) -> Vec<String> {
+        let mut args = vec!["attach".to_string(), "--machine".to_string()];
+
+        if let Some(ref flavor) = self.flavor {
+            args.push("--flavor".to_string());
+            args.push(flavor.clone());
+        }
+
+        if let Some(ref target) = self.target {
+            args.push("--target".to_string());
+            args.push(target.clone());
+        }
+
+        if let Some(ref device) = self.device
+            && device != "auto"
+        {
+            args.push("--device-id".to_string());
+            args.push(device.clone());
+        }
+
+        if let Some(url) = debug_url {
+            args.push("--debug-url".to_string());
+            args.push(url.to_string());
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_start_params_from_config() {
+        let config: Config = toml::from_str(
+            r#"
+            daemon = true
+            flavor = "staging"
+            target = "lib/main_staging.dart"
+            extra_args = ["--profile", "--route", "/settings"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.daemon);
+        assert_eq!(
+            config.app_start_params(Path::new("/src/app")).unwrap(),
+            serde_json::json!({
+                "projectDirectory": "/src/app",
+                "mode": "profile",
+                "hot": true,
+                "flavor": "staging",
+                "target": "lib/main_staging.dart",
+                "route": "/settings",
+            })
+        );
+
+        let unsupported = Config {
+            extra_args: vec!["--web-port=8080".to_string()],
+            ..Config::default()
+        };
+        assert!(unsupported.app_start_params(Path::new("/src/app")).is_err());
+    }
 }
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+
+use crate::vm_service::VmServiceConnection;
+
+/// The `timeDilation` `toggle` switches to from normal speed, as DevTools'
+/// "Slow animations" does.
+const SLOW_ANIMATIONS: f64 = 5.0;
+
+/// A framework debug setting exposed as an `ext.flutter.<name>` service
+/// extension. Names are the extensions' own.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "camelCase")]
+pub enum DebugFlag {
+    DebugPaint,
+    DebugPaintBaselinesEnabled,
+    RepaintRainbow,
+    ShowPerformanceOverlay,
+    DebugAllowBanner,
+    InvertOversizedImages,
+    ProfileWidgetBuilds,
+    DebugDisablePhysicalShapeLayers,
+    DebugDisableOpacityLayers,
+    TimeDilation,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum FlagValue {
+    Bool(bool),
+    Number(f64),
+}
+
+impl std::fmt::Display for FlagValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlagValue::Bool(b) => write!(f, "{b}"),
+            FlagValue::Number(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+impl FlagValue {
+    /// The opposite value: the boolean negated, or `timeDilation` between
+    /// normal speed and slow animations.
+    pub fn toggled(self) -> FlagValue {
+        match self {
+            FlagValue::Bool(b) => FlagValue::Bool(!b),
+            FlagValue::Number(1.0) => FlagValue::Number(SLOW_ANIMATIONS),
+            FlagValue::Number(_) => FlagValue::Number(1.0),
+        }
+    }
+}
+
+impl DebugFlag {
+    pub fn all() -> &'static [DebugFlag] {
+        <DebugFlag as clap::ValueEnum>::value_variants()
+    }
+
+    pub fn name(self) -> String {
+        clap::ValueEnum::to_possible_value(&self)
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The extension's argument holding the value: `enabled` for the
+    /// boolean ones.
+    fn param(self) -> &'static str {
+        match self {
+            DebugFlag::TimeDilation => "timeDilation",
+            _ => "enabled",
+        }
+    }
+
+    /// Parse a value given on the command line: true/false (or on/off), or
+    /// a factor for `timeDilation`.
+    pub fn parse_value(self, value: &str) -> Result<FlagValue> {
+        match self {
+            DebugFlag::TimeDilation => match value.parse::<f64>() {
+                Ok(n) if n > 0.0 => Ok(FlagValue::Number(n)),
+                _ => bail!("timeDilation must be a positive number, e.g. 1 or 5"),
+            },
+            _ => match value.to_ascii_lowercase().as_str() {
+                "true" | "on" => Ok(FlagValue::Bool(true)),
+                "false" | "off" => Ok(FlagValue::Bool(false)),
+                _ => bail!("{} takes true or false, not {value}", self.name()),
+            },
+        }
+    }
+
+    /// Read the value from the extension's response, which holds it as a string.
+    fn value_from(self, response: &serde_json::Value) -> Option<FlagValue> {
+        let value = response.get(self.param())?;
+        let text = match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        self.parse_value(&text).ok()
+    }
+}
+
+/// The current value of a debug setting.
+pub async fn get(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    flag: DebugFlag,
+) -> Result<FlagValue> {
+    call(conn, flag, serde_json::json!({ "isolateId": isolate_id })).await
+}
+
+/// Change a debug setting, returning the value the app reports back.
+pub async fn set(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    flag: DebugFlag,
+    value: FlagValue,
+) -> Result<FlagValue> {
+    let params = serde_json::json!({
+        "isolateId": isolate_id,
+        flag.param(): value.to_string(),
+    });
+    call(conn, flag, params).await
+}
+
+async fn call(
+    conn: &mut VmServiceConnection,
+    flag: DebugFlag,
+    params: serde_json::Value,
+) -> Result<FlagValue> {
+    let method = format!("ext.flutter.{}", flag.name());
+    let response = conn
+        .send(&method, params)
+        .await
+        .with_context(|| format!("{method} failed; is the app running in debug mode?"))?;
+    flag.value_from(&response)
+        .with_context(|| format!("Unexpected {method} response: {response}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_names_and_values() {
+        assert_eq!(
+            DebugFlag::DebugPaintBaselinesEnabled.name(),
+            "debugPaintBaselinesEnabled"
+        );
+        assert_eq!(DebugFlag::TimeDilation.name(), "timeDilation");
+
+        let paint = DebugFlag::DebugPaint;
+        assert_eq!(paint.parse_value("on").unwrap(), FlagValue::Bool(true));
+        assert!(paint.parse_value("2").is_err());
+        assert_eq!(
+            paint.value_from(&serde_json::json!({ "enabled": "false", "type": "_extensionType" })),
+            Some(FlagValue::Bool(false))
+        );
+        assert_eq!(FlagValue::Bool(false).toggled(), FlagValue::Bool(true));
+
+        let dilation = DebugFlag::TimeDilation;
+        assert!(dilation.parse_value("0").is_err());
+        assert_eq!(
+            dilation.value_from(&serde_json::json!({ "timeDilation": "1.0" })),
+            Some(FlagValue::Number(1.0))
+        );
+        assert_eq!(FlagValue::Number(1.0).toggled(), FlagValue::Number(5.0));
+        assert_eq!(FlagValue::Number(2.5).toggled(), FlagValue::Number(1.0));
+    }
+}
@@ -0,0 +1,150 @@
+use serde_json::Value;
+
+use crate::snapshot;
+
+/// Diagnostic levels folded away unless `--verbose` is given. A property
+/// equal to its default value is reported at `fine`.
+const FOLDED_LEVELS: &[&str] = &["hidden", "fine", "off"];
+
+/// Format a `DiagnosticsNode` from `getDetailsSubtree` as an indented tree:
+/// each node's line, its properties as `name: value`, then its children.
+pub fn format_details(node: &Value, verbose: bool) -> String {
+    let mut lines = Vec::new();
+    format_node(node, 0, verbose, &mut lines);
+    lines.join("\n")
+}
+
+fn format_node(node: &Value, depth: usize, verbose: bool, lines: &mut Vec<String>) {
+    let mut line = format!("{}{}", "  ".repeat(depth), str_field(node, "description"));
+    if let Some(value_id) = node.get("valueId").and_then(|v| v.as_str()) {
+        line.push_str(&format!("  [{value_id}]"));
+    }
+    if let Some(loc) = node
+        .get("creationLocation")
+        .and_then(snapshot::parse_location)
+    {
+        line.push_str(&format!(" {}", loc.short()));
+    }
+    lines.push(line);
+
+    format_properties(node, depth + 1, verbose, lines);
+    for child in array_field(node, "children") {
+        format_node(child, depth + 1, verbose, lines);
+    }
+}
+
+fn format_properties(node: &Value, depth: usize, verbose: bool, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let mut folded = 0;
+    for property in array_field(node, "properties") {
+        if !verbose && FOLDED_LEVELS.contains(&level(property)) {
+            folded += 1;
+            continue;
+        }
+        lines.push(format!("{indent}{}", property_line(property)));
+        // Diagnosticable values such as a TextStyle carry their own properties
+        format_properties(property, depth + 1, verbose, lines);
+        for child in array_field(property, "children") {
+            format_node(child, depth + 1, verbose, lines);
+        }
+    }
+    if folded > 0 {
+        let s = if folded == 1 { "y" } else { "ies" };
+        lines.push(format!(
+            "{indent}({folded} hidden or default propert{s}; --verbose shows them)"
+        ));
+    }
+}
+
+/// `name: value`, marked `(default)` when equal to the default value and
+/// tagged with the level unless it is `info`.
+fn property_line(property: &Value) -> String {
+    let description = str_field(property, "description");
+    let show_name = property.get("showName").and_then(|s| s.as_bool()) != Some(false);
+    let mut line = match property.get("name").and_then(|n| n.as_str()) {
+        Some(name) if show_name && !name.is_empty() => {
+            let separator = property.get("showSeparator").and_then(|s| s.as_bool()) != Some(false);
+            format!("{name}{} {description}", if separator { ":" } else { "" })
+        }
+        _ => description.to_string(),
+    };
+    if is_default(property) {
+        line.push_str(" (default)");
+    } else if level(property) != "info" {
+        line.push_str(&format!(" [{}]", level(property)));
+    }
+    line
+}
+
+/// The node's diagnostic level; the inspector leaves out `info`.
+fn level(node: &Value) -> &str {
+    node.get("level").and_then(|l| l.as_str()).unwrap_or("info")
+}
+
+/// Whether a property holds its default value: it has one and was demoted
+/// to `fine` for being uninteresting.
+fn is_default(property: &Value) -> bool {
+    property.get("defaultValue").is_some() && level(property) == "fine"
+}
+
+fn str_field<'a>(node: &'a Value, key: &str) -> &'a str {
+    node.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn array_field<'a>(node: &'a Value, key: &str) -> &'a [Value] {
+    node.get(key)
+        .and_then(|v| v.as_array())
+        .map_or(&[], Vec::as_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_node() -> Value {
+        serde_json::json!({
+            "description": "Text",
+            "valueId": "inspector-12",
+            "creationLocation": { "file": "file:///app/lib/main.dart", "line": 42, "column": 7 },
+            "properties": [
+                { "name": "data", "description": "\"Sign in\"", "type": "StringProperty" },
+                { "name": "textAlign", "description": "null", "defaultValue": "null",
+                  "level": "fine" },
+                { "name": "dependencies", "description": "[MediaQuery]", "level": "hidden" },
+                { "name": "style", "description": "TextStyle(inherit: true)",
+                  "properties": [
+                      { "name": "inherit", "description": "true" },
+                      { "name": "color", "description": "Color(0xff000000)", "level": "debug" },
+                  ] },
+                { "description": "renders nothing", "showName": false, "level": "warning" },
+            ],
+            "children": [
+                { "description": "RichText", "valueId": "inspector-13", "properties": [] },
+            ],
+        })
+    }
+
+    #[test]
+    fn folds_hidden_and_default_properties() {
+        assert_eq!(
+            format_details(&text_node(), false),
+            "\
+Text  [inspector-12] main.dart:42
+  data: \"Sign in\"
+  style: TextStyle(inherit: true)
+    inherit: true
+    color: Color(0xff000000) [debug]
+  renders nothing [warning]
+  (2 hidden or default properties; --verbose shows them)
+  RichText  [inspector-13]"
+        );
+    }
+
+    #[test]
+    fn verbose_shows_levels_and_defaults() {
+        let output = format_details(&text_node(), true);
+        assert!(output.contains("\n  textAlign: null (default)\n"));
+        assert!(output.contains("\n  dependencies: [MediaQuery] [hidden]\n"));
+        assert!(!output.contains("--verbose"));
+    }
+}
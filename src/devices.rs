@@ -0,0 +1,162 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+use crate::machine;
+
+/// How long a one-off `flutter daemon` request may take; launching an
+/// emulator is the slow one.
+const DAEMON_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A device as reported by `flutter devices --machine` or `flutter daemon`'s
+/// `device.getDevices`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    #[serde(default, alias = "targetPlatform")]
+    pub platform: String,
+    #[serde(default)]
+    pub emulator: bool,
+}
+
+/// An emulator or simulator `flutter` can launch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Emulator {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub platform_type: Option<String>,
+}
+
+/// Connected devices, from `flutter devices --machine`.
+pub async fn list_devices() -> Result<Vec<Device>> {
+    let output = Command::new("flutter")
+        .args(["devices", "--machine"])
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run flutter devices: {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!("flutter devices failed ({})", output.status));
+    }
+    parse_devices(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse `flutter devices --machine` output, skipping anything flutter
+/// printed before the JSON array.
+fn parse_devices(stdout: &str) -> Result<Vec<Device>> {
+    let json = stdout
+        .find('[')
+        .map(|start| &stdout[start..])
+        .context("No device list in flutter devices output")?;
+    Ok(serde_json::from_str(json)?)
+}
+
+pub async fn list_emulators() -> Result<Vec<Emulator>> {
+    let result = daemon_request("emulator.getEmulators", serde_json::json!({})).await?;
+    Ok(serde_json::from_value(result)?)
+}
+
+/// Launch an emulator and wait for flutter to report it started.
+pub async fn launch_emulator(id: &str, cold_boot: bool) -> Result<()> {
+    daemon_request(
+        "emulator.launch",
+        serde_json::json!({ "emulatorId": id, "coldBoot": cold_boot }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Send one request to a short-lived `flutter daemon` and return its result.
+async fn daemon_request(method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+    let mut child = Command::new("flutter")
+        .arg("daemon")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start flutter daemon: {e}"))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+    let request = serde_json::json!([{ "id": 1, "method": method, "params": params }]);
+    stdin.write_all(format!("{request}\n").as_bytes()).await?;
+    stdin.flush().await?;
+
+    let response = tokio::time::timeout(DAEMON_TIMEOUT, async {
+        while let Some(line) = lines.next_line().await? {
+            if let Some(event) = machine::parse_machine_line(&line)
+                && event.get("id").and_then(|i| i.as_i64()) == Some(1)
+            {
+                return Ok(event);
+            }
+        }
+        Err(anyhow!(
+            "flutter daemon exited before responding to {method}"
+        ))
+    })
+    .await
+    .map_err(|_| anyhow!("Timeout waiting for flutter daemon to answer {method}"))??;
+
+    let shutdown = serde_json::json!([{ "id": 2, "method": "daemon.shutdown" }]);
+    let _ = stdin.write_all(format!("{shutdown}\n").as_bytes()).await;
+    let _ = tokio::time::timeout(Duration::from_secs(2), child.wait()).await;
+    machine::response_result(method, response)
+}
+
+/// The device `wanted` names (by ID, or by name ignoring case), or the
+/// first device when nothing is configured (`auto` included).
+pub fn pick<'a>(devices: &'a [Device], wanted: Option<&str>) -> Option<&'a Device> {
+    match wanted.filter(|w| *w != "auto") {
+        Some(wanted) => devices
+            .iter()
+            .find(|d| d.id == wanted)
+            .or_else(|| devices.iter().find(|d| d.name.eq_ignore_ascii_case(wanted))),
+        None => devices.first(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_flutter_devices_output() {
+        let stdout = r#"Downloading Material fonts...
+[
+  {"name": "Linux", "id": "linux", "isSupported": true, "targetPlatform": "linux-x64",
+   "emulator": false, "sdk": "Ubuntu 24.04"},
+  {"name": "sdk gphone64 x86 64", "id": "emulator-5554", "targetPlatform": "android-x64",
+   "emulator": true}
+]"#;
+        let devices = parse_devices(stdout).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].platform, "linux-x64");
+        assert!(devices[1].emulator);
+        assert!(parse_devices("No devices found").is_err());
+    }
+
+    #[test]
+    fn pick_by_id_or_name() {
+        let devices: Vec<Device> = serde_json::from_value(serde_json::json!([
+            { "id": "linux", "name": "Linux", "platform": "linux-x64", "emulator": false },
+            { "id": "chrome", "name": "Chrome", "platform": "web-javascript", "emulator": false,
+              "category": "web" },
+        ]))
+        .unwrap();
+
+        assert_eq!(pick(&devices, Some("chrome")).unwrap().id, "chrome");
+        assert_eq!(pick(&devices, Some("CHROME")).unwrap().id, "chrome");
+        assert_eq!(pick(&devices, Some("auto")).unwrap().id, "linux");
+        assert_eq!(pick(&devices, None).unwrap().id, "linux");
+        assert!(pick(&devices, Some("macos")).is_none());
+        assert!(pick(&[], None).is_none());
+    }
+}
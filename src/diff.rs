@@ -0,0 +1,440 @@
+use serde::Serialize;
+
+use crate::snapshot::WidgetNode;
+
+/// One difference between two widget trees. Paths are the widget labels
+/// from the root down, joined with " > ".
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    /// A widget (and its subtree of `widgets` widgets) that only exists after.
+    Added {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        location: Option<String>,
+        widgets: usize,
+    },
+    /// A widget (and its subtree) that only existed before.
+    Removed {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        location: Option<String>,
+        widgets: usize,
+    },
+    /// The same widget under a different parent, or reordered among its siblings.
+    Moved {
+        widget: String,
+        from: String,
+        to: String,
+    },
+    /// A Text widget showing something else.
+    TextChanged {
+        path: String,
+        before: String,
+        after: String,
+    },
+    /// A widget now created at a different source location.
+    LocationChanged {
+        path: String,
+        before: Option<String>,
+        after: Option<String>,
+    },
+}
+
+/// Compare two widget trees. Children are aligned by widget type, preferring
+/// matches with the same source location and text, so an inserted sibling
+/// shows up as one addition rather than a cascade of changes.
+pub fn diff_trees(before: &[WidgetNode], after: &[WidgetNode]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    diff_children(before, after, "", &mut changes, &mut removed, &mut added);
+    pair_moves(removed, added, &mut changes);
+    changes
+}
+
+/// A subtree present on only one side, kept until moves are paired up.
+struct Orphan<'a> {
+    node: &'a WidgetNode,
+    parent: String,
+}
+
+impl Orphan<'_> {
+    fn path(&self) -> String {
+        join_path(&self.parent, &self.node.label())
+    }
+}
+
+fn diff_children<'a>(
+    before: &'a [WidgetNode],
+    after: &'a [WidgetNode],
+    parent: &str,
+    changes: &mut Vec<Change>,
+    removed: &mut Vec<Orphan<'a>>,
+    added: &mut Vec<Orphan<'a>>,
+) {
+    let pairs = align(before, after);
+    let (mut b, mut a) = (0, 0);
+    for (i, j) in pairs.into_iter().chain([(before.len(), after.len())]) {
+        for node in &before[b..i] {
+            removed.push(Orphan {
+                node,
+                parent: parent.to_string(),
+            });
+        }
+        for node in &after[a..j] {
+            added.push(Orphan {
+                node,
+                parent: parent.to_string(),
+            });
+        }
+        if i < before.len() && j < after.len() {
+            diff_node(&before[i], &after[j], parent, changes, removed, added);
+        }
+        (b, a) = (i + 1, j + 1);
+    }
+}
+
+fn diff_node<'a>(
+    before: &'a WidgetNode,
+    after: &'a WidgetNode,
+    parent: &str,
+    changes: &mut Vec<Change>,
+    removed: &mut Vec<Orphan<'a>>,
+    added: &mut Vec<Orphan<'a>>,
+) {
+    let path = join_path(parent, &after.label());
+    if before.text() != after.text() {
+        changes.push(Change::TextChanged {
+            path: join_path(parent, &after.widget_type),
+            before: before.text().unwrap_or("").to_string(),
+            after: after.text().unwrap_or("").to_string(),
+        });
+    }
+    if before.creation_location != after.creation_location {
+        changes.push(Change::LocationChanged {
+            path: path.clone(),
+            before: before.creation_location.as_ref().map(|l| l.short()),
+            after: after.creation_location.as_ref().map(|l| l.short()),
+        });
+    }
+    diff_children(
+        &before.children,
+        &after.children,
+        &path,
+        changes,
+        removed,
+        added,
+    );
+}
+
+/// Report a removed and an added subtree of the same widget as a move, and
+/// the rest as removals and additions.
+fn pair_moves(removed: Vec<Orphan>, mut added: Vec<Orphan>, changes: &mut Vec<Change>) {
+    let mut moves = Vec::new();
+    let mut removals = Vec::new();
+    for orphan in removed {
+        let twin = added.iter().position(|a| same_widget(orphan.node, a.node));
+        match twin {
+            Some(index) => moves.push((orphan, added.remove(index))),
+            None => removals.push(orphan),
+        }
+    }
+
+    for orphan in removals {
+        changes.push(Change::Removed {
+            path: orphan.path(),
+            location: location(orphan.node),
+            widgets: orphan.node.count(),
+        });
+    }
+    for orphan in added {
+        changes.push(Change::Added {
+            path: orphan.path(),
+            location: location(orphan.node),
+            widgets: orphan.node.count(),
+        });
+    }
+    for (from, to) in moves {
+        changes.push(Change::Moved {
+            widget: to.node.label(),
+            from: from.parent.clone(),
+            to: to.parent.clone(),
+        });
+        // Report what changed inside the moved subtree too
+        let mut inner_removed = Vec::new();
+        let mut inner_added = Vec::new();
+        diff_node(
+            from.node,
+            to.node,
+            &to.parent,
+            changes,
+            &mut inner_removed,
+            &mut inner_added,
+        );
+        pair_moves(inner_removed, inner_added, changes);
+    }
+}
+
+fn same_widget(a: &WidgetNode, b: &WidgetNode) -> bool {
+    a.widget_type == b.widget_type
+        && a.creation_location == b.creation_location
+        && a.text() == b.text()
+}
+
+fn location(node: &WidgetNode) -> Option<String> {
+    node.creation_location.as_ref().map(|l| l.short())
+}
+
+fn join_path(parent: &str, label: &str) -> String {
+    if parent.is_empty() {
+        label.to_string()
+    } else {
+        format!("{parent} > {label}")
+    }
+}
+
+/// How well two siblings correspond; zero if they can't be the same widget.
+fn similarity(a: &WidgetNode, b: &WidgetNode) -> u32 {
+    if a.widget_type != b.widget_type {
+        return 0;
+    }
+    let mut score = 4;
+    if a.creation_location == b.creation_location {
+        score += 2;
+    }
+    if a.text() == b.text() {
+        score += 1;
+    }
+    score
+}
+
+/// Index pairs of the best order-preserving matching between two sibling lists.
+fn align(before: &[WidgetNode], after: &[WidgetNode]) -> Vec<(usize, usize)> {
+    let (n, m) = (before.len(), after.len());
+    // best[i][j]: best total similarity of before[i..] against after[j..]
+    let mut best = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            let score = similarity(&before[i], &after[j]);
+            let matched = if score > 0 {
+                best[i + 1][j + 1] + score
+            } else {
+                0
+            };
+            best[i][j] = matched.max(best[i + 1][j]).max(best[i][j + 1]);
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        let score = similarity(&before[i], &after[j]);
+        if score > 0 && best[i][j] == best[i + 1][j + 1] + score {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if best[i][j] == best[i + 1][j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// One line per change.
+pub fn format_changes(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No changes".to_string();
+    }
+    changes
+        .iter()
+        .map(format_change)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_change(change: &Change) -> String {
+    match change {
+        Change::Added {
+            path,
+            location,
+            widgets,
+        } => format!("+ {path}{}", subtree_suffix(location, *widgets)),
+        Change::Removed {
+            path,
+            location,
+            widgets,
+        } => format!("- {path}{}", subtree_suffix(location, *widgets)),
+        Change::Moved { widget, from, to } if from == to => {
+            format!("> {widget} reordered within {}", or_root(to))
+        }
+        Change::Moved { widget, from, to } => {
+            format!("> {widget} moved from {} to {}", or_root(from), or_root(to))
+        }
+        Change::TextChanged {
+            path,
+            before,
+            after,
+        } => format!("~ {path} text {before:?} -> {after:?}"),
+        Change::LocationChanged {
+            path,
+            before,
+            after,
+        } => format!(
+            "@ {path} created at {} -> {}",
+            before.as_deref().unwrap_or("?"),
+            after.as_deref().unwrap_or("?")
+        ),
+    }
+}
+
+fn subtree_suffix(location: &Option<String>, widgets: usize) -> String {
+    let mut suffix = String::new();
+    if let Some(location) = location {
+        suffix.push_str(&format!(" {location}"));
+    }
+    if widgets > 1 {
+        suffix.push_str(&format!(" ({widgets} widgets)"));
+    }
+    suffix
+}
+
+fn or_root(path: &str) -> &str {
+    if path.is_empty() { "(root)" } else { path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::CreationLocation;
+
+    fn widget(widget_type: &str, line: u32, children: Vec<WidgetNode>) -> WidgetNode {
+        WidgetNode {
+            widget_type: widget_type.to_string(),
+            value_id: String::new(),
+            description: String::new(),
+            creation_location: Some(CreationLocation {
+                file: "file:///app/lib/home.dart".to_string(),
+                line,
+                column: 5,
+            }),
+            children,
+        }
+    }
+
+    fn text(text: &str, line: u32) -> WidgetNode {
+        WidgetNode {
+            description: format!("Text \"{text}\""),
+            ..widget("Text", line, vec![])
+        }
+    }
+
+    #[test]
+    fn identical_trees() {
+        let tree = vec![widget("App", 1, vec![text("Hello", 2)])];
+        assert!(diff_trees(&tree, &tree).is_empty());
+        assert_eq!(format_changes(&[]), "No changes");
+    }
+
+    #[test]
+    fn inserted_sibling_is_one_addition() {
+        let before = vec![widget("Column", 1, vec![text("A", 2), text("B", 3)])];
+        let after = vec![widget(
+            "Column",
+            1,
+            vec![
+                widget("Row", 9, vec![text("New", 10)]),
+                text("A", 2),
+                text("B", 3),
+            ],
+        )];
+        assert_eq!(
+            diff_trees(&before, &after),
+            vec![Change::Added {
+                path: "Column > Row".to_string(),
+                location: Some("home.dart:9".to_string()),
+                widgets: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn changed_text_and_location() {
+        let before = vec![widget("App", 1, vec![text("Hello", 2)])];
+        let after = vec![widget("App", 1, vec![text("Hi", 3)])];
+        let changes = diff_trees(&before, &after);
+        assert_eq!(
+            format_changes(&changes),
+            "~ App > Text text \"Hello\" -> \"Hi\"\n\
+             @ App > Text \"Hi\" created at home.dart:2 -> home.dart:3"
+        );
+    }
+
+    #[test]
+    fn removed_subtree() {
+        let before = vec![widget(
+            "App",
+            1,
+            vec![widget("Banner", 4, vec![text("Sale", 5)]), text("Body", 6)],
+        )];
+        let after = vec![widget("App", 1, vec![text("Body", 6)])];
+        assert_eq!(
+            format_changes(&diff_trees(&before, &after)),
+            "- App > Banner home.dart:4 (2 widgets)"
+        );
+    }
+
+    #[test]
+    fn reordered_siblings() {
+        let before = vec![widget(
+            "Row",
+            1,
+            vec![text("A", 2), widget("Icon", 3, vec![])],
+        )];
+        let after = vec![widget(
+            "Row",
+            1,
+            vec![widget("Icon", 3, vec![]), text("A", 2)],
+        )];
+        let changes = diff_trees(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert!(format_changes(&changes).ends_with("reordered within Row"));
+    }
+
+    #[test]
+    fn moved_widget() {
+        let card = widget("Card", 7, vec![text("Title", 8)]);
+        let before = vec![widget(
+            "App",
+            1,
+            vec![
+                widget("Column", 2, vec![card.clone()]),
+                widget("Row", 3, vec![]),
+            ],
+        )];
+        let mut moved = card;
+        moved.children[0] = text("New title", 8);
+        let after = vec![widget(
+            "App",
+            1,
+            vec![widget("Column", 2, vec![]), widget("Row", 3, vec![moved])],
+        )];
+        let changes = diff_trees(&before, &after);
+        assert_eq!(
+            format_changes(&changes),
+            "> Card moved from App > Column to App > Row\n\
+             ~ App > Row > Card > Text text \"Title\" -> \"New title\""
+        );
+        assert_eq!(
+            serde_json::json!(changes[0]),
+            serde_json::json!({
+                "change": "moved",
+                "widget": "Card",
+                "from": "App > Column",
+                "to": "App > Row",
+            })
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::vm_service::VmServiceConnection;
+
+/// Library whose scope has the widgets, rendering and gestures layers plus
+/// `WidgetInspectorService` in view. Used for expressions the CLI generates.
+pub const INSPECTOR_LIBRARY: &str = "package:flutter/src/widgets/widget_inspector.dart";
+
+/// Find the ID of a loaded library by its URI.
+pub async fn library_id(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    uri: &str,
+) -> Result<String> {
+    let isolate = conn
+        .send("getIsolate", serde_json::json!({ "isolateId": isolate_id }))
+        .await?;
+
+    isolate
+        .get("libraries")
+        .and_then(|l| l.as_array())
+        .into_iter()
+        .flatten()
+        .find(|lib| lib.get("uri").and_then(|u| u.as_str()) == Some(uri))
+        .and_then(|lib| lib.get("id").and_then(|i| i.as_str()))
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Library {uri} is not loaded in the Flutter isolate"))
+}
+
+/// Evaluate a Dart expression against a library or object, returning its `InstanceRef`.
+pub async fn evaluate(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target_id: &str,
+    expression: &str,
+) -> Result<serde_json::Value> {
+    let result = conn
+        .send(
+            "evaluate",
+            serde_json::json!({
+                "isolateId": isolate_id,
+                "targetId": target_id,
+                "expression": expression,
+                "disableBreakpoints": true,
+            }),
+        )
+        .await?;
+    check_error(result)
+}
+
+/// Evaluate an expression that produces a String and return its full value.
+pub async fn evaluate_string(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target_id: &str,
+    expression: &str,
+) -> Result<String> {
+    let instance = evaluate(conn, isolate_id, target_id, expression).await?;
+    if instance.get("kind").and_then(|k| k.as_str()) != Some("String") {
+        return Err(anyhow!(
+            "Expected a String result, got {}",
+            instance
+                .get("kind")
+                .and_then(|k| k.as_str())
+                .unwrap_or("unknown")
+        ));
+    }
+
+    // InstanceRefs only carry a prefix of long strings
+    let truncated = instance
+        .get("valueAsStringIsTruncated")
+        .and_then(|t| t.as_bool())
+        .unwrap_or(false);
+    let instance = match instance.get("id").and_then(|i| i.as_str()) {
+        Some(object_id) if truncated => {
+            conn.send(
+                "getObject",
+                serde_json::json!({ "isolateId": isolate_id, "objectId": object_id }),
+            )
+            .await?
+        }
+        _ => instance,
+    };
+
+    instance
+        .get("valueAsString")
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Evaluation returned no string value"))
+}
+
+/// What an `eval` expression is evaluated against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalTarget {
+    /// The app's root library (usually `main.dart`).
+    RootLibrary,
+    /// A loaded library, by URI.
+    Library(String),
+    /// The widget, or with `state` its `State`, behind an inspector value ID.
+    Object { value_id: String, state: bool },
+    /// A frame on the stack of the paused isolate.
+    Frame(u32),
+}
+
+/// Evaluate an expression for `eval`, returning its `InstanceRef`.
+pub async fn evaluate_target(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target: &EvalTarget,
+    expression: &str,
+) -> Result<serde_json::Value> {
+    let target_id = match target {
+        EvalTarget::RootLibrary => root_library_id(conn, isolate_id).await?,
+        EvalTarget::Library(uri) => library_id(conn, isolate_id, uri).await?,
+        EvalTarget::Object { value_id, state } => {
+            let library = library_id(conn, isolate_id, INSPECTOR_LIBRARY).await?;
+            let object = evaluate(
+                conn,
+                isolate_id,
+                &library,
+                &object_expression(value_id, *state),
+            )
+            .await?;
+            object
+                .get("id")
+                .and_then(|i| i.as_str())
+                .map(String::from)
+                .ok_or_else(|| anyhow!("No object behind value ID {value_id}"))?
+        }
+        EvalTarget::Frame(index) => {
+            let result = conn
+                .send(
+                    "evaluateInFrame",
+                    serde_json::json!({
+                        "isolateId": isolate_id,
+                        "frameIndex": index,
+                        "expression": expression,
+                        "disableBreakpoints": true,
+                    }),
+                )
+                .await?;
+            return check_error(result);
+        }
+    };
+    evaluate(conn, isolate_id, &target_id, expression).await
+}
+
+async fn root_library_id(conn: &mut VmServiceConnection, isolate_id: &str) -> Result<String> {
+    let isolate = conn
+        .send("getIsolate", serde_json::json!({ "isolateId": isolate_id }))
+        .await?;
+    isolate
+        .get("rootLib")
+        .and_then(|l| l.get("id"))
+        .and_then(|i| i.as_str())
+        .map(String::from)
+        .ok_or_else(|| anyhow!("The Flutter isolate has no root library"))
+}
+
+/// Dart expression resolving an inspector value ID to its widget or state.
+fn object_expression(value_id: &str, state: bool) -> String {
+    let id = dart_string(value_id);
+    if state {
+        format!(
+            "(() {{ final o = WidgetInspectorService.instance.toObject({id}); \
+             if (o is StatefulElement) return o.state; \
+             throw StateError('not a stateful widget'); }})()"
+        )
+    } else {
+        format!(
+            "(() {{ final o = WidgetInspectorService.instance.toObject({id}); \
+             return o is Element ? o.widget : o; }})()"
+        )
+    }
+}
+
+/// A value from the VM, with fields expanded down to some depth.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueNode {
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<NamedValue>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NamedValue {
+    pub name: String,
+    pub value: ValueNode,
+}
+
+/// Elements and entries fetched per expanded list or map.
+const MAX_ELEMENTS: usize = 100;
+
+impl ValueNode {
+    /// Describe an `InstanceRef` (or `Sentinel`) without fetching anything.
+    pub fn from_ref(instance: &serde_json::Value) -> Self {
+        let str_field = |key: &str| instance.get(key).and_then(|v| v.as_str()).map(String::from);
+        let kind = if str_field("type").as_deref() == Some("Sentinel") {
+            "Sentinel".to_string()
+        } else {
+            str_field("kind").unwrap_or_else(|| "Unknown".to_string())
+        };
+        ValueNode {
+            kind,
+            class_name: instance
+                .get("class")
+                .and_then(|c| c.get("name"))
+                .and_then(|n| n.as_str())
+                .map(String::from),
+            value: str_field("valueAsString"),
+            truncated: instance
+                .get("valueAsStringIsTruncated")
+                .and_then(|t| t.as_bool())
+                .unwrap_or(false),
+            length: instance.get("length").and_then(|l| l.as_i64()),
+            id: str_field("id"),
+            fields: Vec::new(),
+        }
+    }
+
+    fn is_primitive(&self) -> bool {
+        matches!(
+            self.kind.as_str(),
+            "Null" | "Bool" | "Int" | "Double" | "String" | "Sentinel"
+        )
+    }
+
+    /// One-line rendering of this value, without its fields.
+    fn summary(&self) -> String {
+        let class = self.class_name.as_deref().unwrap_or(&self.kind);
+        match self.kind.as_str() {
+            "Null" => "null".to_string(),
+            "String" => {
+                let value = format!("{:?}", self.value.as_deref().unwrap_or(""));
+                if self.truncated {
+                    format!("{}...\"", &value[..value.len() - 1])
+                } else {
+                    value
+                }
+            }
+            _ if self.is_primitive() => self.value.clone().unwrap_or_default(),
+            _ => {
+                let mut out = class.to_string();
+                if let Some(length) = self.length {
+                    out.push_str(&format!(" (length {length})"));
+                }
+                if let Some(ref value) = self.value {
+                    out.push_str(&format!(" {value}"));
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Describe an `InstanceRef`, fetching fields, elements and entries `depth` levels down.
+pub async fn expand(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    instance: &serde_json::Value,
+    depth: usize,
+) -> Result<ValueNode> {
+    let mut node = ValueNode::from_ref(instance);
+    let fetch_string = node.kind == "String" && node.truncated;
+    if (depth == 0 || node.is_primitive()) && !fetch_string {
+        return Ok(node);
+    }
+    let Some(object_id) = node.id.clone() else {
+        return Ok(node);
+    };
+
+    let object = conn
+        .send(
+            "getObject",
+            serde_json::json!({
+                "isolateId": isolate_id,
+                "objectId": object_id,
+                "count": MAX_ELEMENTS,
+            }),
+        )
+        .await?;
+    if fetch_string {
+        return Ok(ValueNode::from_ref(&object));
+    }
+
+    let empty = Vec::new();
+    let list = |key: &str| object.get(key).and_then(|v| v.as_array()).unwrap_or(&empty);
+    let mut children = Vec::new();
+    for field in list("fields") {
+        let name = field
+            .get("name")
+            .and_then(|n| {
+                n.as_str()
+                    .map(String::from)
+                    .or_else(|| n.as_i64().map(|i| format!("${i}")))
+            })
+            .or_else(|| {
+                field
+                    .get("decl")
+                    .and_then(|d| d.get("name"))
+                    .and_then(|n| n.as_str())
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| "?".to_string());
+        children.push((name, field.get("value").cloned().unwrap_or_default()));
+    }
+    for (index, element) in list("elements").iter().enumerate() {
+        children.push((format!("[{index}]"), element.clone()));
+    }
+    for association in list("associations") {
+        let key = ValueNode::from_ref(association.get("key").unwrap_or(&serde_json::Value::Null));
+        children.push((
+            format!("[{}]", key.summary()),
+            association.get("value").cloned().unwrap_or_default(),
+        ));
+    }
+
+    for (name, value) in children {
+        let value = Box::pin(expand(conn, isolate_id, &value, depth - 1)).await?;
+        node.fields.push(NamedValue { name, value });
+    }
+    Ok(node)
+}
+
+/// Render a value as indented text, one field per line.
+pub fn format_value(node: &ValueNode) -> String {
+    let mut out = node.summary();
+    format_fields(node, 1, &mut out);
+    out
+}
+
+fn format_fields(node: &ValueNode, indent: usize, out: &mut String) {
+    for field in &node.fields {
+        out.push_str(&format!(
+            "\n{}{}: {}",
+            "  ".repeat(indent),
+            field.name,
+            field.value.summary()
+        ));
+        format_fields(&field.value, indent + 1, out);
+    }
+}
+
+/// Turn an `@Error` result (compilation error, unhandled exception) into an error.
+fn check_error(result: serde_json::Value) -> Result<serde_json::Value> {
+    let kind = result.get("type").and_then(|t| t.as_str());
+    if matches!(kind, Some("@Error") | Some("Error")) {
+        let message = result
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("unknown error");
+        return Err(anyhow!("Evaluation failed: {message}"));
+    }
+    Ok(result)
+}
+
+/// Quote a string as a Dart single-quoted string literal.
+pub fn dart_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '$' => out.push_str("\\$"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dart_string_escapes() {
+        assert_eq!(dart_string("Sign in"), "'Sign in'");
+        assert_eq!(dart_string("it's $5\\n"), r"'it\'s \$5\\n'");
+        assert_eq!(dart_string("a\nb"), r"'a\nb'");
+    }
+
+    #[test]
+    fn error_results() {
+        let err = check_error(serde_json::json!({
+            "type": "@Error",
+            "kind": "CompilationError",
+            "message": "Undefined name 'foo'",
+        }))
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Evaluation failed: Undefined name 'foo'");
+        assert!(check_error(serde_json::json!({ "type": "@Instance", "kind": "Int" })).is_ok());
+    }
+
+    #[test]
+    fn object_expressions() {
+        assert_eq!(
+            object_expression("inspector-7", false),
+            "(() { final o = WidgetInspectorService.instance.toObject('inspector-7'); \
+             return o is Element ? o.widget : o; })()"
+        );
+        assert!(object_expression("inspector-7", true).contains("return o.state;"));
+    }
+
+    #[test]
+    fn format_expanded_value() {
+        let mut node = ValueNode::from_ref(&serde_json::json!({
+            "type": "@Instance",
+            "kind": "PlainInstance",
+            "id": "objects/12",
+            "class": { "type": "@Class", "name": "_CounterState" },
+        }));
+        let field = |name: &str, value: serde_json::Value| NamedValue {
+            name: name.to_string(),
+            value: ValueNode::from_ref(&value),
+        };
+        let mut items = field(
+            "_items",
+            serde_json::json!({ "kind": "List", "length": 2, "class": { "name": "_GrowableList" } }),
+        );
+        items.value.fields = vec![
+            field(
+                "[0]",
+                serde_json::json!({ "kind": "Int", "valueAsString": "1" }),
+            ),
+            field(
+                "[1]",
+                serde_json::json!({ "kind": "Null", "valueAsString": "null" }),
+            ),
+        ];
+        node.fields = vec![
+            field(
+                "_counter",
+                serde_json::json!({ "kind": "Int", "valueAsString": "3" }),
+            ),
+            field(
+                "label",
+                serde_json::json!({
+                    "kind": "String",
+                    "valueAsString": "Say \"hi\"",
+                    "valueAsStringIsTruncated": true,
+                }),
+            ),
+            field(
+                "_late",
+                serde_json::json!({ "type": "Sentinel", "kind": "NotInitialized", "valueAsString": "<not initialized>" }),
+            ),
+            items,
+        ];
+
+        assert_eq!(
+            format_value(&node),
+            "_CounterState\n\
+             \x20\x20_counter: 3\n\
+             \x20\x20label: \"Say \\\"hi\\\"...\"\n\
+             \x20\x20_late: <not initialized>\n\
+             \x20\x20_items: _GrowableList (length 2)\n\
+             \x20\x20\x20\x20[0]: 1\n\
+             \x20\x20\x20\x20[1]: null"
+        );
+        let json = serde_json::to_value(&node).unwrap();
+        assert_eq!(json["className"], "_CounterState");
+        assert_eq!(json["fields"][1]["value"]["truncated"], true);
+        assert!(json["fields"][0]["value"].get("fields").is_none());
+    }
+}
@@ -0,0 +1,682 @@
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+use crate::eval::{self, dart_string};
+use crate::vm_service::VmServiceConnection;
+
+/// A widget to interact with, as given on the command line.
+///
+/// Accepts `id:`, `label:`, `text:` and `key:` prefixes. Without a prefix,
+/// `inspector-N` is a value ID from `snapshot` and anything else matches a
+/// semantics label, visible text or key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    ValueId(String),
+    Label(String),
+    Text(String),
+    Key(String),
+    Any(String),
+}
+
+impl Target {
+    pub fn parse(spec: &str) -> Self {
+        if let Some((prefix, rest)) = spec.split_once(':') {
+            match prefix {
+                "id" => return Target::ValueId(rest.to_string()),
+                "label" => return Target::Label(rest.to_string()),
+                "text" => return Target::Text(rest.to_string()),
+                "key" => return Target::Key(rest.to_string()),
+                _ => {}
+            }
+        }
+        let is_value_id = spec
+            .strip_prefix("inspector-")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+        if is_value_id {
+            Target::ValueId(spec.to_string())
+        } else {
+            Target::Any(spec.to_string())
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Target::ValueId(id) => format!("value ID {id}"),
+            Target::Label(label) => format!("semantics label \"{label}\""),
+            Target::Text(text) => format!("text \"{text}\""),
+            Target::Key(key) => format!("key \"{key}\""),
+            Target::Any(query) => format!("\"{query}\""),
+        }
+    }
+
+    /// Dart statements collecting matching elements into `matches`.
+    fn find_statements(&self) -> String {
+        if let Target::ValueId(id) = self {
+            return format!(
+                "try {{ final obj = WidgetInspectorService.instance.toObject({}); \
+                 if (obj is Element) matches.add(obj); }} catch (_) {{}}",
+                dart_string(id)
+            );
+        }
+
+        let text = |q: &str| {
+            format!(
+                "(e is RenderObjectElement && ((e.renderObject is RenderParagraph && \
+                 (e.renderObject as RenderParagraph).text.toPlainText() == {q}) || \
+                 (e.renderObject is RenderEditable && \
+                 (e.renderObject as RenderEditable).text?.toPlainText() == {q})))"
+            )
+        };
+        let label = |q: &str| {
+            format!(
+                "((w is Semantics && w.properties.label == {q}) || \
+                 (e is RenderObjectElement && e.renderObject.debugSemantics?.label == {q}))"
+            )
+        };
+        let key = |q: &str| {
+            format!(
+                "(w.key != null && (w.key is ValueKey \
+                 ? '${{(w.key as ValueKey).value}}' == {q} : w.key.toString() == {q}))"
+            )
+        };
+        let condition = match self {
+            Target::ValueId(_) => unreachable!(),
+            Target::Label(q) => label(&dart_string(q)),
+            Target::Text(q) => text(&dart_string(q)),
+            Target::Key(q) => key(&dart_string(q)),
+            Target::Any(q) => {
+                let q = dart_string(q);
+                format!("{} || {} || {}", label(&q), text(&q), key(&q))
+            }
+        };
+        format!(
+            "void visit(Element e) {{ final w = e.widget; \
+             if ({condition}) matches.add(e); e.visitChildren(visit); }} \
+             final root = WidgetsBinding.instance.rootElement; \
+             if (root != null) visit(root);"
+        )
+    }
+}
+
+/// Build an expression that finds the target's first visible, hit-testable
+/// match and runs `action` on it.
+///
+/// `action` is a block of Dart statements that can use `e` (Element), `ro`
+/// (RenderBox), `view` (RenderView), `rect` (global Rect), `center` (Offset)
+/// and `viewId`, and must return a `Map<String, Object?>`.
+fn locate_expression(target: &Target, action: &str) -> String {
+    format!(
+        "(() {{ \
+         final matches = <Element>[]; \
+         {find} \
+         String? problem; \
+         for (final e in matches) {{ \
+           final ro = e.renderObject; \
+           if (ro is! RenderBox || !ro.attached || !ro.hasSize) {{ problem ??= 'it is not laid out'; continue; }} \
+           RenderObject? node = ro; \
+           while (node != null && node is! RenderView) {{ node = node.parent; }} \
+           if (node is! RenderView) {{ problem ??= 'it is not attached to a view'; continue; }} \
+           final view = node; \
+           final viewId = view.flutterView.viewId; \
+           final rect = Rect.fromPoints(ro.localToGlobal(Offset.zero), ro.localToGlobal(ro.size.bottomRight(Offset.zero))); \
+           final visible = rect.intersect(Offset.zero & view.size); \
+           if (visible.width <= 0 || visible.height <= 0) {{ problem ??= 'it is offscreen at $rect'; continue; }} \
+           final center = visible.center; \
+           final hit = HitTestResult(); \
+           WidgetsBinding.instance.hitTestInView(hit, center, viewId); \
+           if (!hit.path.any((entry) => entry.target == ro)) {{ \
+             problem ??= 'it is obscured at $center by ${{hit.path.isEmpty ? 'nothing' : hit.path.first.target.runtimeType}}'; \
+             continue; \
+           }} \
+           final result = <String, Object?>{{ \
+             'widget': e.widget.runtimeType.toString(), \
+             'rect': [rect.left, rect.top, rect.width, rect.height], \
+             'x': center.dx, 'y': center.dy, \
+           }}; \
+           result.addAll((() {{ {action} }})()); \
+           return json.encode(result); \
+         }} \
+         return json.encode({{'error': matches.isEmpty ? 'no widget matches' : problem}}); \
+         }})()",
+        find = target.find_statements(),
+    )
+}
+
+/// Dart statements dispatching a pointer down/up at `center`.
+const TAP_ACTION: &str = "\
+    final pointer = 900000 + DateTime.now().microsecondsSinceEpoch % 100000; \
+    GestureBinding.instance.handlePointerEvent(PointerDownEvent(pointer: pointer, position: center, viewId: viewId)); \
+    GestureBinding.instance.handlePointerEvent(PointerUpEvent(pointer: pointer, position: center, viewId: viewId)); \
+    return <String, Object?>{};";
+
+/// Where an interaction happened, as reported by the app.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Interaction {
+    pub widget: String,
+    /// Global rect in logical pixels: left, top, width, height.
+    pub rect: [f64; 4],
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Text entered into a field, as reported by the app.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TextEntry {
+    pub widget: String,
+    /// The field's full text after the edit.
+    pub text: String,
+    pub submitted: bool,
+}
+
+/// Dart function `findState(Element start)` returning the first `State` named
+/// `type_name` below `start`, or failing that above it.
+///
+/// Most framework states are not in the inspector library's scope, so they are
+/// matched by type name and driven through dynamic calls.
+fn state_finder(type_name: &str) -> String {
+    format!(
+        "bool isWanted(Element x) => x is StatefulElement && x.state.runtimeType.toString() == '{type_name}'; \
+         dynamic findState(Element start) {{ \
+           dynamic found; \
+           void down(Element x) {{ if (found != null) return; if (isWanted(x)) {{ found = (x as StatefulElement).state; return; }} x.visitChildren(down); }} \
+           down(start); \
+           if (found == null) start.visitAncestorElements((a) {{ if (isWanted(a)) {{ found = (a as StatefulElement).state; return false; }} return true; }}); \
+           return found; \
+         }}"
+    )
+}
+
+/// Build an expression that finds the `EditableText` at or around the target
+/// (or the focused one), focuses it and replaces or appends to its text.
+fn enter_text_expression(
+    target: Option<&Target>,
+    text: &str,
+    append: bool,
+    submit: bool,
+) -> String {
+    let (find, widget, missing) = match target {
+        Some(target) => (target.find_statements(), "e.widget", "no widget matches"),
+        None => (
+            "final focused = WidgetsBinding.instance.focusManager.primaryFocus?.context; \
+             if (focused is Element) matches.add(focused);"
+                .to_string(),
+            "s.widget",
+            "no widget has focus",
+        ),
+    };
+    let submit = if submit {
+        "final action = s.widget.textInputAction; \
+         if (action != null) { s.performAction(action); } \
+         else { if (s.widget.onEditingComplete != null) { s.widget.onEditingComplete(); } \
+         else { s.widget.focusNode.unfocus(); } s.widget.onSubmitted?.call(next); }"
+    } else {
+        ""
+    };
+    format!(
+        "(() {{ \
+         final matches = <Element>[]; \
+         {find} \
+         {finder} \
+         for (final e in matches) {{ \
+           final s = findState(e); \
+           if (s == null) continue; \
+           if (s.widget.readOnly == true) return json.encode({{'error': 'it is read-only'}}); \
+           s.requestKeyboard(); \
+           final current = s.textEditingValue; \
+           final String next = {append} ? current.text + {text} : {text}; \
+           s.userUpdateTextEditingValue(current.copyWith(text: next, \
+             selection: current.selection.copyWith(baseOffset: next.length, extentOffset: next.length)), null); \
+           {submit} \
+           return json.encode({{'widget': {widget}.runtimeType.toString(), 'text': next, 'submitted': {submitted}}}); \
+         }} \
+         return json.encode({{'error': matches.isEmpty ? '{missing}' : 'it is not a text field'}}); \
+         }})()",
+        finder = state_finder("EditableTextState"),
+        text = dart_string(text),
+        submitted = !submit.is_empty(),
+    )
+}
+
+/// A synthesized one-finger drag, in logical pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct Drag {
+    pub dx: f64,
+    pub dy: f64,
+    pub duration: Duration,
+    /// Move past the touch slop first and hold still before lifting, so a
+    /// scrollable moves by exactly (dx, dy) and does not fling.
+    pub precise: bool,
+}
+
+/// `kTouchSlop` from package:flutter/gestures.
+const TOUCH_SLOP: f64 = 18.0;
+/// Longer than the 40ms after which `VelocityTracker` treats the pointer as stopped.
+const RELEASE_HOLD: Duration = Duration::from_millis(60);
+/// Time for the frames following a drag to apply it.
+const SETTLE: Duration = Duration::from_millis(100);
+const SCROLL_DURATION: Duration = Duration::from_millis(300);
+
+impl Drag {
+    /// Time from pointer down until the pointer is released.
+    fn total_time(&self) -> Duration {
+        if self.precise {
+            self.duration + RELEASE_HOLD
+        } else {
+            self.duration
+        }
+    }
+}
+
+/// Dart statements that press at `center` and move by the drag's offset in
+/// ~16ms steps, reporting the scroll offset of the enclosing scrollable.
+///
+/// Moves after the first are scheduled with `Future.delayed`, so the
+/// expression returns before the drag finishes.
+fn drag_action(drag: &Drag) -> String {
+    let steps = (drag.duration.as_millis() / 16).max(1);
+    let step_us = drag.duration.as_micros() / steps;
+    let release_us = step_us * steps + (drag.total_time() - drag.duration).as_micros();
+    let length = drag.dx.hypot(drag.dy);
+    let (slop_x, slop_y) = if drag.precise && length > 0.0 {
+        (drag.dx / length * TOUCH_SLOP, drag.dy / length * TOUCH_SLOP)
+    } else {
+        (0.0, 0.0)
+    };
+    format!(
+        "final pointer = 900000 + DateTime.now().microsecondsSinceEpoch % 100000; \
+         Duration stamp() => Duration(microseconds: DateTime.now().microsecondsSinceEpoch); \
+         void send(PointerEvent event) => GestureBinding.instance.handlePointerEvent(event); \
+         final start = center + Offset({slop_x:?}, {slop_y:?}); \
+         var last = center; \
+         send(PointerDownEvent(pointer: pointer, position: center, viewId: viewId, timeStamp: stamp())); \
+         if (start != center) {{ \
+           send(PointerMoveEvent(pointer: pointer, position: start, delta: start - center, viewId: viewId, timeStamp: stamp())); \
+           last = start; \
+         }} \
+         for (var i = 1; i <= {steps}; i++) {{ \
+           Future.delayed(Duration(microseconds: {step_us} * i), () {{ \
+             final next = start + Offset({dx:?}, {dy:?}) * (i / {steps}); \
+             send(PointerMoveEvent(pointer: pointer, position: next, delta: next - last, viewId: viewId, timeStamp: stamp())); \
+             last = next; \
+           }}); \
+         }} \
+         Future.delayed(Duration(microseconds: {release_us}), () => \
+           send(PointerUpEvent(pointer: pointer, position: last, viewId: viewId, timeStamp: stamp()))); \
+         {finder} \
+         return <String, Object?>{{'pixels': findState(e)?.position.pixels}};",
+        dx = drag.dx,
+        dy = drag.dy,
+        finder = state_finder("ScrollableState"),
+    )
+}
+
+/// Build an expression returning the scroll offset of the target's scrollable
+/// as JSON, or `null` if it has none.
+fn pixels_expression(target: &Target) -> String {
+    format!(
+        "(() {{ \
+         final matches = <Element>[]; \
+         {find} \
+         {finder} \
+         for (final e in matches) {{ \
+           final s = findState(e); \
+           if (s != null) return json.encode(s.position.pixels); \
+         }} \
+         return 'null'; \
+         }})()",
+        find = target.find_statements(),
+        finder = state_finder("ScrollableState"),
+    )
+}
+
+/// A drag as reported by the app.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DragStart {
+    #[serde(flatten)]
+    pub at: Interaction,
+    /// Scroll offset of the enclosing scrollable before the drag.
+    pub pixels: Option<f64>,
+}
+
+/// A finished scroll and the scrollable's offset before and after.
+#[derive(Debug, Clone)]
+pub struct Scroll {
+    pub at: Interaction,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+}
+
+/// The isolate and library that interaction expressions are evaluated in.
+struct Scope {
+    isolate_id: String,
+    library: String,
+}
+
+impl Scope {
+    async fn resolve(conn: &mut VmServiceConnection, isolate_id: &str) -> Result<Self> {
+        let library = eval::library_id(conn, isolate_id, eval::INSPECTOR_LIBRARY).await?;
+        Ok(Self {
+            isolate_id: isolate_id.to_string(),
+            library,
+        })
+    }
+
+    async fn evaluate(&self, conn: &mut VmServiceConnection, expression: &str) -> Result<String> {
+        eval::evaluate_string(conn, &self.isolate_id, &self.library, expression).await
+    }
+
+    async fn interact<T: DeserializeOwned>(
+        &self,
+        conn: &mut VmServiceConnection,
+        expression: &str,
+        subject: &str,
+        verb: &str,
+    ) -> Result<T> {
+        let result = self.evaluate(conn, expression).await?;
+        parse_result(&result, subject, verb)
+    }
+
+    /// Drag from the center of the target and wait until the pointer is released.
+    async fn drag(
+        &self,
+        conn: &mut VmServiceConnection,
+        target: &Target,
+        drag: &Drag,
+        verb: &str,
+    ) -> Result<DragStart> {
+        let expression = locate_expression(target, &drag_action(drag));
+        let start = self
+            .interact(conn, &expression, &target.describe(), verb)
+            .await?;
+        tokio::time::sleep(drag.total_time() + SETTLE).await;
+        Ok(start)
+    }
+
+    async fn pixels(&self, conn: &mut VmServiceConnection, target: &Target) -> Result<Option<f64>> {
+        let result = self.evaluate(conn, &pixels_expression(target)).await?;
+        Ok(serde_json::from_str(&result)?)
+    }
+}
+
+/// Evaluate an interaction expression in the Flutter isolate.
+async fn interact<T: DeserializeOwned>(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    expression: &str,
+    subject: &str,
+    verb: &str,
+) -> Result<T> {
+    let scope = Scope::resolve(conn, isolate_id).await?;
+    scope.interact(conn, expression, subject, verb).await
+}
+
+/// Split an expression's JSON result into its value or the app's reason for
+/// not acting.
+fn parse_reply(result: &str) -> Result<std::result::Result<serde_json::Value, String>> {
+    let value: serde_json::Value = serde_json::from_str(result)?;
+    Ok(match value.get("error") {
+        Some(error) => Err(error.as_str().unwrap_or("no usable match").to_string()),
+        None => Ok(value),
+    })
+}
+
+fn parse_result<T: DeserializeOwned>(result: &str, subject: &str, verb: &str) -> Result<T> {
+    match parse_reply(result)? {
+        Ok(value) => Ok(serde_json::from_value(value)?),
+        Err(reason) => Err(anyhow!("Cannot {verb} {subject}: {reason}")),
+    }
+}
+
+/// Tap the center of the target's visible area.
+pub async fn tap(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target: &Target,
+) -> Result<Interaction> {
+    let expression = locate_expression(target, TAP_ACTION);
+    interact(conn, isolate_id, &expression, &target.describe(), "tap").await
+}
+
+/// Drag from the center of the target's visible area.
+pub async fn drag(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target: &Target,
+    drag: &Drag,
+) -> Result<Interaction> {
+    let scope = Scope::resolve(conn, isolate_id).await?;
+    Ok(scope.drag(conn, target, drag, "drag").await?.at)
+}
+
+/// Scroll the target's scrollable by (dx, dy): positive values reveal content
+/// further down or right.
+pub async fn scroll(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target: &Target,
+    dx: f64,
+    dy: f64,
+) -> Result<Scroll> {
+    let scope = Scope::resolve(conn, isolate_id).await?;
+    let drag = scroll_drag(dx, dy);
+    let start = scope.drag(conn, target, &drag, "scroll").await?;
+    let to = scope.pixels(conn, target).await?;
+    Ok(Scroll {
+        at: start.at,
+        from: start.pixels,
+        to,
+    })
+}
+
+/// Scroll the target's scrollable by (dx, dy) at a time until `find` is
+/// visible and hit-testable, returning where it was found and how many
+/// scrolls it took.
+pub async fn scroll_until_visible(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target: &Target,
+    find: &Target,
+    dx: f64,
+    dy: f64,
+    max_scrolls: u32,
+) -> Result<(Interaction, u32)> {
+    let scope = Scope::resolve(conn, isolate_id).await?;
+    let locate = locate_expression(find, "return <String, Object?>{};");
+    let drag = scroll_drag(dx, dy);
+    let mut scrolls = 0;
+    loop {
+        let reason = match parse_reply(&scope.evaluate(conn, &locate).await?)? {
+            Ok(value) => return Ok((serde_json::from_value(value)?, scrolls)),
+            Err(reason) => reason,
+        };
+        if scrolls == max_scrolls {
+            bail!(
+                "{} is not visible after {scrolls} scrolls: {reason}",
+                find.describe()
+            );
+        }
+        let start = scope.drag(conn, target, &drag, "scroll").await?;
+        scrolls += 1;
+        let to = scope.pixels(conn, target).await?;
+        if to.is_some() && to == start.pixels {
+            bail!(
+                "Reached the end of {} after {scrolls} scrolls without finding {}",
+                target.describe(),
+                find.describe()
+            );
+        }
+    }
+}
+
+/// The finger moves against the scroll direction.
+fn scroll_drag(dx: f64, dy: f64) -> Drag {
+    Drag {
+        dx: -dx,
+        dy: -dy,
+        duration: SCROLL_DURATION,
+        precise: true,
+    }
+}
+
+/// Focus the target's text field, or the focused one, and replace or append to its text.
+pub async fn enter_text(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    target: Option<&Target>,
+    text: &str,
+    append: bool,
+    submit: bool,
+) -> Result<TextEntry> {
+    let expression = enter_text_expression(target, text, append, submit);
+    let subject = target.map_or_else(|| "the focused widget".to_string(), Target::describe);
+    interact(conn, isolate_id, &expression, &subject, "enter text into").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_targets() {
+        assert_eq!(
+            Target::parse("inspector-12"),
+            Target::ValueId("inspector-12".to_string())
+        );
+        assert_eq!(
+            Target::parse("label:Sign in"),
+            Target::Label("Sign in".to_string())
+        );
+        assert_eq!(
+            Target::parse("key:login_button"),
+            Target::Key("login_button".to_string())
+        );
+        assert_eq!(Target::parse("text:a:b"), Target::Text("a:b".to_string()));
+        assert_eq!(Target::parse("Sign in"), Target::Any("Sign in".to_string()));
+        assert_eq!(
+            Target::parse("inspector-"),
+            Target::Any("inspector-".to_string())
+        );
+        assert_eq!(
+            Target::parse("http://x"),
+            Target::Any("http://x".to_string())
+        );
+    }
+
+    #[test]
+    fn find_by_value_id() {
+        let find = Target::parse("inspector-3").find_statements();
+        assert!(find.contains("WidgetInspectorService.instance.toObject('inspector-3')"));
+    }
+
+    #[test]
+    fn find_quotes_query() {
+        let find = Target::Text("it's".to_string()).find_statements();
+        assert!(find.contains(r"toPlainText() == 'it\'s'"));
+        assert!(!find.contains("ValueKey"));
+    }
+
+    #[test]
+    fn interaction_result() {
+        let subject = Target::parse("Submit").describe();
+        let ok: Interaction = parse_result(
+            r#"{"widget":"ElevatedButton","rect":[10.0,20.0,100.0,40.0],"x":60.0,"y":40.0}"#,
+            &subject,
+            "tap",
+        )
+        .unwrap();
+        assert_eq!(ok.widget, "ElevatedButton");
+        assert_eq!(ok.x, 60.0);
+
+        let err = parse_result::<Interaction>(
+            r#"{"error":"it is offscreen at Rect.fromLTRB(0.0, 900.0, 100.0, 940.0)"}"#,
+            &subject,
+            "tap",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot tap \"Submit\": it is offscreen at Rect.fromLTRB(0.0, 900.0, 100.0, 940.0)"
+        );
+    }
+
+    #[test]
+    fn enter_text_into_focused_field() {
+        let expr = enter_text_expression(None, "a@b.c", false, false);
+        assert!(expr.contains("primaryFocus?.context"));
+        assert!(expr.contains("final String next = false ? current.text + 'a@b.c' : 'a@b.c';"));
+        assert!(expr.contains("'submitted': false"));
+        assert!(!expr.contains("performAction"));
+
+        let expr = enter_text_expression(Some(&Target::parse("key:email")), "x", true, true);
+        assert!(expr.contains("== 'email'"));
+        assert!(expr.contains("s.performAction(action)"));
+        assert!(expr.contains("'submitted': true"));
+    }
+
+    #[test]
+    fn text_entry_result() {
+        let entry: TextEntry = parse_result(
+            r#"{"widget":"TextField","text":"hello","submitted":true}"#,
+            "key \"email\"",
+            "enter text into",
+        )
+        .unwrap();
+        assert_eq!(entry.text, "hello");
+        assert!(entry.submitted);
+
+        let err = parse_result::<TextEntry>(
+            r#"{"error":"it is not a text field"}"#,
+            "the focused widget",
+            "enter text into",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot enter text into the focused widget: it is not a text field"
+        );
+    }
+
+    #[test]
+    fn scroll_drags_against_scroll_direction() {
+        let action = drag_action(&scroll_drag(0.0, 300.0));
+        // 300ms in 16ms steps, released after holding still
+        assert!(action.contains("i <= 18;"));
+        assert!(action.contains("Duration(microseconds: 16666 * i)"));
+        assert!(action.contains("Duration(microseconds: 359988)"));
+        assert!(action.contains("center + Offset(-0.0, -18.0)"));
+        assert!(action.contains("start + Offset(-0.0, -300.0) * (i / 18)"));
+    }
+
+    #[test]
+    fn plain_drag_skips_slop_and_hold() {
+        let drag = Drag {
+            dx: 120.0,
+            dy: 0.0,
+            duration: Duration::from_millis(10),
+            precise: false,
+        };
+        assert_eq!(drag.total_time(), Duration::from_millis(10));
+        let action = drag_action(&drag);
+        assert!(action.contains("center + Offset(0.0, 0.0)"));
+        assert!(action.contains("i <= 1;"));
+        assert!(action.contains("Duration(microseconds: 10000)"));
+    }
+
+    #[test]
+    fn drag_result() {
+        let start: DragStart = parse_result(
+            r#"{"widget":"ListView","rect":[0.0,0.0,400.0,800.0],"x":200.0,"y":400.0,"pixels":null}"#,
+            "key \"list\"",
+            "scroll",
+        )
+        .unwrap();
+        assert_eq!(start.at.widget, "ListView");
+        assert_eq!(start.pixels, None);
+        assert_eq!(
+            parse_reply(r#"{"error":"it is obscured"}"#).unwrap(),
+            Err("it is obscured".to_string())
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+
+/// An 8-bit RGBA image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA samples.
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = ((y * self.width + x) * 4) as usize;
+        Some(self.rgba[i..i + 4].try_into().unwrap())
+    }
+}
+
+/// Decode a PNG of any color type into RGBA.
+pub fn decode_png(bytes: &[u8]) -> Result<Image> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().context("Not a PNG image")?;
+    let mut buf = vec![0; reader.output_buffer_size().context("PNG image too large")?];
+    let info = reader.next_frame(&mut buf).context("Corrupt PNG image")?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => bail!("Unexpanded palette in PNG image"),
+    };
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
+
+pub fn encode_png(image: &Image) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.rgba)?;
+    writer.finish()?;
+    Ok(bytes)
+}
+
+/// What `screenshot --compare` checks against.
+pub struct CompareOptions {
+    pub golden: String,
+    /// Per-channel difference still counted as a match.
+    pub tolerance: u8,
+    /// Percentage of differing pixels still accepted.
+    pub threshold: f64,
+    /// Where to write the diff image; next to the screenshot by default.
+    pub diff_path: Option<String>,
+    /// Overwrite the golden instead of comparing.
+    pub update: bool,
+}
+
+/// Default diff image path: `shot.png` -> `shot.diff.png`.
+pub fn default_diff_path(screenshot_path: &str) -> String {
+    let stem = screenshot_path
+        .strip_suffix(".png")
+        .unwrap_or(screenshot_path);
+    format!("{stem}.diff.png")
+}
+
+/// How a screenshot compares with its golden image.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// Pixels whose channels differ by more than the tolerance, counting
+    /// pixels outside the overlap when the sizes differ.
+    pub differing: u64,
+    pub total: u64,
+    /// Largest per-channel difference seen.
+    pub max_delta: u8,
+    pub golden_size: (u32, u32),
+    pub actual_size: (u32, u32),
+}
+
+impl Comparison {
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.differing as f64 * 100.0 / self.total as f64
+    }
+
+    /// Whether the differing share is within `threshold` percent.
+    pub fn passes(&self, threshold: f64) -> bool {
+        self.golden_size == self.actual_size && self.percent() <= threshold
+    }
+}
+
+const DIFF_COLOR: [u8; 4] = [255, 0, 0, 255];
+
+/// Compare pixel by pixel, allowing each channel to differ by `tolerance`.
+/// The diff image shows differing pixels in red over a faded copy of the
+/// screenshot.
+pub fn compare(golden: &Image, actual: &Image, tolerance: u8) -> (Comparison, Image) {
+    let width = golden.width.max(actual.width);
+    let height = golden.height.max(actual.height);
+    let mut diff = Image {
+        width,
+        height,
+        rgba: Vec::with_capacity((width * height * 4) as usize),
+    };
+    let mut differing = 0;
+    let mut max_delta = 0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = match (golden.pixel(x, y), actual.pixel(x, y)) {
+                (Some(expected), Some(got)) => {
+                    let delta = (0..4).map(|c| expected[c].abs_diff(got[c])).max().unwrap();
+                    max_delta = max_delta.max(delta);
+                    if delta > tolerance {
+                        differing += 1;
+                        DIFF_COLOR
+                    } else {
+                        faded(got)
+                    }
+                }
+                // Outside one of the images
+                _ => {
+                    differing += 1;
+                    DIFF_COLOR
+                }
+            };
+            diff.rgba.extend_from_slice(&pixel);
+        }
+    }
+
+    let comparison = Comparison {
+        differing,
+        total: u64::from(width) * u64::from(height),
+        max_delta,
+        golden_size: (golden.width, golden.height),
+        actual_size: (actual.width, actual.height),
+    };
+    (comparison, diff)
+}
+
+/// A matching pixel, washed out so the red differences stand out.
+fn faded(pixel: [u8; 4]) -> [u8; 4] {
+    let luma =
+        (u32::from(pixel[0]) * 299 + u32::from(pixel[1]) * 587 + u32::from(pixel[2]) * 114) / 1000;
+    let washed = (255 - (255 - luma) / 4) as u8;
+    [washed, washed, washed, 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> Image {
+        Image {
+            width,
+            height,
+            rgba: pixel.repeat((width * height) as usize),
+        }
+    }
+
+    #[test]
+    fn png_roundtrip() {
+        let mut image = solid(3, 2, [10, 20, 30, 255]);
+        image.rgba[4..8].copy_from_slice(&[200, 100, 50, 128]);
+        let decoded = decode_png(&encode_png(&image).unwrap()).unwrap();
+        assert_eq!(decoded, image);
+        assert!(decode_png(b"not a png").is_err());
+    }
+
+    #[test]
+    fn decode_rgb_as_rgba() {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 1, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1, 2, 3]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(decode_png(&bytes).unwrap().rgba, vec![1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn tolerance_and_threshold() {
+        let golden = solid(2, 2, [100, 100, 100, 255]);
+        let mut actual = golden.clone();
+        actual.rgba[0] = 103;
+        actual.rgba[12] = 150;
+
+        let (comparison, diff) = compare(&golden, &actual, 3);
+        assert_eq!(comparison.differing, 1);
+        assert_eq!(comparison.max_delta, 50);
+        assert_eq!(comparison.percent(), 25.0);
+        assert!(comparison.passes(25.0));
+        assert!(!comparison.passes(10.0));
+        assert_eq!(diff.pixel(1, 1), Some(DIFF_COLOR));
+        assert_ne!(diff.pixel(0, 0), Some(DIFF_COLOR));
+
+        let (comparison, _) = compare(&golden, &actual, 0);
+        assert_eq!(comparison.differing, 2);
+    }
+
+    #[test]
+    fn diff_path_next_to_screenshot() {
+        assert_eq!(default_diff_path("/tmp/shot.png"), "/tmp/shot.diff.png");
+        assert_eq!(default_diff_path("shot"), "shot.diff.png");
+    }
+
+    #[test]
+    fn size_mismatch_fails() {
+        let golden = solid(2, 2, [0, 0, 0, 255]);
+        let actual = solid(3, 2, [0, 0, 0, 255]);
+        let (comparison, diff) = compare(&golden, &actual, 0);
+        assert_eq!(comparison.differing, 2);
+        assert_eq!(comparison.total, 6);
+        assert!(!comparison.passes(100.0));
+        assert_eq!((diff.width, diff.height), (3, 2));
+        assert_eq!(diff.pixel(2, 0), Some(DIFF_COLOR));
+    }
+}
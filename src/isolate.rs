@@ -1,10 +1,39 @@
 use anyhow::{Result, anyhow};
+use serde::Serialize;
 
 use crate::vm_service::VmServiceConnection;
 
-/// Discover the Flutter isolate by finding one with ext.flutter.* extensions.
-/// Returns the isolate ID.
-pub async fn find_flutter_isolate(conn: &mut VmServiceConnection) -> Result<String> {
+/// Selector value that goes back to picking the Flutter isolate automatically.
+pub const AUTO: &str = "auto";
+
+/// An isolate of the app's VM, as listed by `isolates`.
+#[derive(Serialize, Debug, Clone)]
+pub struct IsolateInfo {
+    pub id: String,
+    pub name: String,
+    pub number: String,
+    pub extensions: Vec<String>,
+}
+
+impl IsolateInfo {
+    pub fn is_flutter(&self) -> bool {
+        self.extensions
+            .iter()
+            .any(|ext| ext.starts_with("ext.flutter"))
+    }
+
+    /// Whether `--isolate <selector>` picks this isolate: by ID (with or
+    /// without the `isolates/` prefix), number or name.
+    pub fn matches(&self, selector: &str) -> bool {
+        self.id == selector
+            || self.id.strip_prefix("isolates/") == Some(selector)
+            || self.number == selector
+            || self.name == selector
+    }
+}
+
+/// IDs, names and numbers of the VM's isolates, without their details.
+async fn isolate_refs(conn: &mut VmServiceConnection) -> Result<Vec<IsolateInfo>> {
     let vm = conn.send("getVM", serde_json::json!({})).await?;
 
     let isolates = vm
@@ -12,13 +41,85 @@ pub async fn find_flutter_isolate(conn: &mut VmServiceConnection) -> Result<Stri
         .and_then(|i| i.as_array())
         .ok_or_else(|| anyhow!("No isolates in VM response"))?;
 
-    for isolate_ref in isolates {
-        let Some(id) = isolate_ref.get("id").and_then(|i| i.as_str()) else {
-            continue;
-        };
+    let field = |isolate: &serde_json::Value, key: &str| {
+        isolate
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    Ok(isolates
+        .iter()
+        .filter(|i| i.get("id").is_some_and(|id| id.is_string()))
+        .map(|isolate| IsolateInfo {
+            id: field(isolate, "id"),
+            name: field(isolate, "name"),
+            number: field(isolate, "number"),
+            extensions: Vec::new(),
+        })
+        .collect())
+}
 
+/// Every isolate with the service extensions it registered.
+pub async fn list_isolates(conn: &mut VmServiceConnection) -> Result<Vec<IsolateInfo>> {
+    let mut isolates = isolate_refs(conn).await?;
+    for info in &mut isolates {
         let isolate = conn
-            .send("getIsolate", serde_json::json!({ "isolateId": id }))
+            .send("getIsolate", serde_json::json!({ "isolateId": info.id }))
+            .await?;
+        info.extensions = isolate
+            .get("extensionRPCs")
+            .and_then(|e| e.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+    Ok(isolates)
+}
+
+/// Find the isolate picked by a `--isolate` selector. Returns the isolate ID.
+pub async fn select_isolate(conn: &mut VmServiceConnection, selector: &str) -> Result<String> {
+    let isolates = isolate_refs(conn).await?;
+    let mut matching = isolates.iter().filter(|i| i.matches(selector));
+    match (matching.next(), matching.next()) {
+        (Some(isolate), None) => Ok(isolate.id.clone()),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Several isolates match \"{selector}\"; select one by ID or number (see `isolates`)"
+        )),
+        (None, _) => Err(anyhow!(
+            "No isolate matches \"{selector}\" (see `isolates`, or use --isolate {AUTO})"
+        )),
+    }
+}
+
+/// Start time of a running isolate, or None if the VM no longer has it.
+/// This is the cheap check for reusing an isolate found on an earlier run.
+pub async fn start_time(conn: &mut VmServiceConnection, isolate_id: &str) -> Option<i64> {
+    let isolate = conn
+        .send("getIsolate", serde_json::json!({ "isolateId": isolate_id }))
+        .await
+        .ok()?;
+    running_start_time(&isolate)
+}
+
+/// `startTime` of a `getIsolate` result; None for the Sentinel of an exited isolate.
+fn running_start_time(isolate: &serde_json::Value) -> Option<i64> {
+    if isolate.get("type").and_then(|t| t.as_str()) == Some("Sentinel") {
+        return None;
+    }
+    isolate.get("startTime").and_then(|t| t.as_i64())
+}
+
+/// Discover the Flutter isolate by finding one with ext.flutter.* extensions.
+/// Returns the isolate ID.
+pub async fn find_flutter_isolate(conn: &mut VmServiceConnection) -> Result<String> {
+    for info in isolate_refs(conn).await? {
+        let isolate = conn
+            .send("getIsolate", serde_json::json!({ "isolateId": info.id }))
             .await?;
 
         let extensions = isolate
@@ -28,7 +129,7 @@ pub async fn find_flutter_isolate(conn: &mut VmServiceConnection) -> Result<Stri
             .unwrap_or_default();
 
         if extensions.iter().any(|ext| ext.starts_with("ext.flutter")) {
-            return Ok(id.to_string());
+            return Ok(info.id);
         }
     }
 
@@ -36,3 +137,37 @@ pub async fn find_flutter_isolate(conn: &mut VmServiceConnection) -> Result<Stri
         "No Flutter isolate found. Is a Flutter app running?"
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selector_matches_id_number_or_name() {
+        let isolate = IsolateInfo {
+            id: "isolates/4123".to_string(),
+            name: "main".to_string(),
+            number: "4123".to_string(),
+            extensions: vec!["ext.flutter.reassemble".to_string()],
+        };
+        assert!(isolate.is_flutter());
+        for selector in ["isolates/4123", "4123", "main"] {
+            assert!(isolate.matches(selector), "{selector}");
+        }
+        assert!(!isolate.matches("background"));
+        assert!(!isolate.matches("isolates/"));
+    }
+
+    #[test]
+    fn start_time_of_running_isolate() {
+        let running = serde_json::json!({
+            "type": "Isolate",
+            "id": "isolates/4123",
+            "startTime": 1760000000000_i64,
+        });
+        assert_eq!(running_start_time(&running), Some(1760000000000));
+
+        let exited = serde_json::json!({ "type": "Sentinel", "kind": "Collected" });
+        assert_eq!(running_start_time(&exited), None);
+    }
+}
@@ -0,0 +1,629 @@
+use serde_json::Value;
+
+use crate::details;
+use crate::snapshot;
+
+/// Columns of the flex diagram.
+const DIAGRAM_WIDTH: usize = 60;
+
+/// Overflows smaller than this are rounding noise.
+const OVERFLOW_EPSILON: f64 = 0.5;
+
+/// Layout of a widget as reported by `getLayoutExplorerNode`. The inspector
+/// sends every number as a string, `Infinity` included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutNode {
+    pub description: String,
+    pub value_id: Option<String>,
+    pub location: Option<String>,
+    pub render_object: Option<String>,
+    pub constraints: Option<Constraints>,
+    pub size: Option<(f64, f64)>,
+    /// Offset within the parent, for children of non-flex boxes.
+    pub offset: Option<(f64, f64)>,
+    pub flex_factor: Option<f64>,
+    pub flex_fit: Option<String>,
+    /// Set when the render object is a flex (Row, Column, Flex).
+    pub flex: Option<FlexLayout>,
+    pub children: Vec<LayoutNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraints {
+    Box {
+        min_width: f64,
+        max_width: f64,
+        min_height: f64,
+        max_height: f64,
+    },
+    /// Non-box constraints (e.g. sliver), as described by the framework.
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlexLayout {
+    pub direction: String,
+    pub main_axis_alignment: Option<String>,
+    pub main_axis_size: Option<String>,
+    pub cross_axis_alignment: Option<String>,
+}
+
+impl FlexLayout {
+    fn is_horizontal(&self) -> bool {
+        self.direction == "horizontal"
+    }
+}
+
+/// `FlexFit` of a flex child. Values are named as in Dart.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "camelCase")]
+pub enum FlexFit {
+    Tight,
+    Loose,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "camelCase")]
+pub enum MainAxisAlignment {
+    Start,
+    End,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "camelCase")]
+pub enum CrossAxisAlignment {
+    Start,
+    End,
+    Center,
+    Stretch,
+    Baseline,
+}
+
+/// Layout changes for `set-flex`: the factor and fit of a flex child, the
+/// alignments of a flex.
+#[derive(Debug, Clone, Default)]
+pub struct FlexChange {
+    pub factor: Option<u32>,
+    pub fit: Option<FlexFit>,
+    pub main_axis: Option<MainAxisAlignment>,
+    pub cross_axis: Option<CrossAxisAlignment>,
+}
+
+impl FlexChange {
+    /// The `flexFit` argument of `setFlexFit`, e.g. `FlexFit.tight`.
+    pub fn fit_arg(&self) -> Option<String> {
+        self.fit.map(|fit| dart_enum("FlexFit", &value_name(&fit)))
+    }
+
+    /// The `mainAxisAlignment` and `crossAxisAlignment` arguments of
+    /// `setFlexProperties`, which always takes both: an alignment left
+    /// unchanged is taken from the flex's `current` layout.
+    pub fn alignment_args(&self, current: Option<&FlexLayout>) -> Option<(String, String)> {
+        let main = self
+            .main_axis
+            .map(|a| value_name(&a))
+            .or_else(|| current?.main_axis_alignment.clone())?;
+        let cross = self
+            .cross_axis
+            .map(|a| value_name(&a))
+            .or_else(|| current?.cross_axis_alignment.clone())?;
+        Some((
+            dart_enum("MainAxisAlignment", &main),
+            dart_enum("CrossAxisAlignment", &cross),
+        ))
+    }
+}
+
+fn value_name(value: &impl clap::ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// The inspector matches enum arguments against Dart's `toString()`.
+fn dart_enum(type_name: &str, value: &str) -> String {
+    format!("{type_name}.{value}")
+}
+
+impl LayoutNode {
+    pub fn parse(node: &Value) -> LayoutNode {
+        let render = node.get("renderObject");
+        LayoutNode {
+            description: str_field(node, "description").unwrap_or("?").to_string(),
+            value_id: str_field(node, "valueId").map(String::from),
+            location: node
+                .get("creationLocation")
+                .and_then(snapshot::parse_location)
+                .map(|loc| loc.short()),
+            render_object: render
+                .and_then(|r| str_field(r, "description"))
+                .map(String::from),
+            constraints: node.get("constraints").map(parse_constraints),
+            size: node.get("size").and_then(|s| pair(s, "width", "height")),
+            offset: node
+                .get("parentData")
+                .and_then(|p| pair(p, "offsetX", "offsetY")),
+            flex_factor: node.get("flexFactor").and_then(number),
+            flex_fit: str_field(node, "flexFit").map(String::from),
+            flex: render.and_then(parse_flex),
+            children: node
+                .get("children")
+                .and_then(|c| c.as_array())
+                .map(|arr| arr.iter().map(LayoutNode::parse).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Size along the parent flex's main and cross axes.
+    fn axes(&self, horizontal: bool) -> Option<(f64, f64)> {
+        let (width, height) = self.size?;
+        Some(if horizontal {
+            (width, height)
+        } else {
+            (height, width)
+        })
+    }
+
+    /// Children sticking out of this box, most important first.
+    pub fn overflows(&self) -> Vec<String> {
+        let Some((width, height)) = self.size else {
+            return Vec::new();
+        };
+        let mut found = Vec::new();
+
+        if let Some(ref flex) = self.flex {
+            let horizontal = flex.is_horizontal();
+            let (main, cross) = self.axes(horizontal).unwrap();
+            let used: f64 = self
+                .children
+                .iter()
+                .filter_map(|c| c.axes(horizontal))
+                .map(|(m, _)| m)
+                .sum();
+            if used - main > OVERFLOW_EPSILON {
+                found.push(format!(
+                    "{} overflowed by {}px {}",
+                    self.description,
+                    px(used - main),
+                    if horizontal {
+                        "horizontally"
+                    } else {
+                        "vertically"
+                    }
+                ));
+            }
+            for child in &self.children {
+                if let Some((_, child_cross)) = child.axes(horizontal)
+                    && child_cross - cross > OVERFLOW_EPSILON
+                {
+                    found.push(format!(
+                        "{} overflows {} by {}px on the cross axis",
+                        child.description,
+                        self.description,
+                        px(child_cross - cross)
+                    ));
+                }
+            }
+            return found;
+        }
+
+        for child in &self.children {
+            let (Some((x, y)), Some((w, h))) = (child.offset, child.size) else {
+                continue;
+            };
+            let sides = [
+                (-x, "left"),
+                (-y, "top"),
+                (x + w - width, "right"),
+                (y + h - height, "bottom"),
+            ];
+            for (amount, side) in sides {
+                if amount > OVERFLOW_EPSILON {
+                    found.push(format!(
+                        "{} overflows {} by {}px on the {side}",
+                        child.description,
+                        self.description,
+                        px(amount)
+                    ));
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Format the layout report: overflows first, then the widget's
+/// constraints, size and flex settings, a diagram of flex children and
+/// the children's sizes. `verbose` adds the render object's properties.
+pub fn format_layout(raw: &Value, verbose: bool) -> String {
+    let node = LayoutNode::parse(raw);
+    let mut lines = vec![title(&node)];
+
+    for overflow in node.overflows() {
+        lines.push(format!("! {overflow}"));
+    }
+    if let Some(ref render) = node.render_object {
+        lines.push(format!("Render object: {render}"));
+    }
+    if let Some(ref constraints) = node.constraints {
+        lines.push(format!("Constraints: {}", format_constraints(constraints)));
+    }
+    if let Some((width, height)) = node.size {
+        lines.push(format!("Size: {} x {}", px(width), px(height)));
+    }
+    if let Some((x, y)) = node.offset {
+        lines.push(format!("Offset in parent: ({}, {})", px(x), px(y)));
+    }
+    if let Some(factor) = node.flex_factor {
+        lines.push(format!(
+            "Flex factor: {}",
+            flex_factor(factor, &node.flex_fit)
+        ));
+    }
+    if let Some(ref flex) = node.flex {
+        lines.push(format!("Flex: {}", format_flex(flex)));
+        lines.extend(diagram(&node, flex));
+    }
+
+    if !node.children.is_empty() {
+        lines.push("Children:".to_string());
+        for child in &node.children {
+            lines.push(format!("  {}", child_line(child)));
+        }
+    }
+
+    if verbose && let Some(render) = raw.get("renderObject") {
+        lines.push("Render object properties:".to_string());
+        let properties = details::format_details(render, true);
+        lines.extend(properties.lines().skip(1).map(String::from));
+    }
+    lines.join("\n")
+}
+
+fn title(node: &LayoutNode) -> String {
+    let mut line = node.description.clone();
+    if let Some(ref id) = node.value_id {
+        line.push_str(&format!("  [{id}]"));
+    }
+    if let Some(ref location) = node.location {
+        line.push_str(&format!(" {location}"));
+    }
+    line
+}
+
+fn child_line(child: &LayoutNode) -> String {
+    let mut line = title(child);
+    if let Some((width, height)) = child.size {
+        line.push_str(&format!("  {} x {}", px(width), px(height)));
+    }
+    if let Some((x, y)) = child.offset {
+        line.push_str(&format!(" at ({}, {})", px(x), px(y)));
+    }
+    if let Some(factor) = child.flex_factor {
+        line.push_str(&format!("  flex {}", flex_factor(factor, &child.flex_fit)));
+    }
+    if let Some(ref constraints) = child.constraints {
+        line.push_str(&format!("  ({})", format_constraints(constraints)));
+    }
+    line
+}
+
+fn flex_factor(factor: f64, fit: &Option<String>) -> String {
+    match fit {
+        Some(fit) => format!("{} ({fit})", px_number(factor)),
+        None => px_number(factor),
+    }
+}
+
+fn format_flex(flex: &FlexLayout) -> String {
+    let mut parts = vec![flex.direction.clone()];
+    if let Some(ref main) = flex.main_axis_alignment {
+        parts.push(format!("main axis {main}"));
+    }
+    if let Some(ref size) = flex.main_axis_size {
+        parts.push(format!("main size {size}"));
+    }
+    if let Some(ref cross) = flex.cross_axis_alignment {
+        parts.push(format!("cross axis {cross}"));
+    }
+    parts.join(", ")
+}
+
+fn format_constraints(constraints: &Constraints) -> String {
+    match constraints {
+        Constraints::Box {
+            min_width,
+            max_width,
+            min_height,
+            max_height,
+        } => {
+            let range = |min: f64, max: f64| {
+                if min == max {
+                    px(min)
+                } else {
+                    format!("{}..{}", px(min), px(max))
+                }
+            };
+            format!(
+                "width {}, height {}",
+                range(*min_width, *max_width),
+                range(*min_height, *max_height)
+            )
+        }
+        Constraints::Other(description) => description.clone(),
+    }
+}
+
+/// The flex children laid out along the main axis, scaled to the wider of
+/// the flex and its children, with the flex's own extent marked below and
+/// any overflow filled with `!`:
+///
+/// ```text
+/// |Text 200          |Expanded 232            |
+/// +---------- Row 390 ---------------------+!!!
+/// ```
+fn diagram(node: &LayoutNode, flex: &FlexLayout) -> Vec<String> {
+    let horizontal = flex.is_horizontal();
+    let Some((main, _)) = node.axes(horizontal) else {
+        return Vec::new();
+    };
+    let sizes: Vec<(&LayoutNode, f64)> = node
+        .children
+        .iter()
+        .filter_map(|c| c.axes(horizontal).map(|(m, _)| (c, m)))
+        .collect();
+    let used: f64 = sizes.iter().map(|(_, m)| m).sum();
+    let total = main.max(used);
+    if sizes.is_empty() || total <= 0.0 || !total.is_finite() {
+        return Vec::new();
+    }
+    let scale = DIAGRAM_WIDTH as f64 / total;
+    let column = |extent: f64| (extent * scale).round() as usize;
+
+    let mut children = String::new();
+    let mut start = 0.0;
+    for (child, extent) in &sizes {
+        let from = column(start);
+        start += extent;
+        let width = column(start).saturating_sub(from).max(1);
+        let label = format!("{} {}", short_name(&child.description), px(*extent));
+        let label: String = label.chars().take(width - 1).collect();
+        children.push('|');
+        children.push_str(&format!("{label:<0$}", width - 1));
+    }
+    children.push('|');
+
+    let end = column(main);
+    let columns = column(total);
+    let mut extent: Vec<char> = (0..=columns)
+        .map(|i| if i > end { '!' } else { '-' })
+        .collect();
+    extent[0] = '+';
+    extent[end] = '+';
+    let label = format!(" {} {} ", short_name(&node.description), px(main));
+    if label.chars().count() + 2 < end {
+        let at = (end - label.chars().count()) / 2;
+        for (i, c) in label.chars().enumerate() {
+            extent[at + i] = c;
+        }
+    }
+
+    let axis = if horizontal { "horizontal" } else { "vertical" };
+    vec![
+        format!("Main axis ({axis}):"),
+        format!("  {children}"),
+        format!("  {}", extent.into_iter().collect::<String>()),
+    ]
+}
+
+/// Widget name without its key or arguments, e.g. `Text` for `Text-[<'a'>]`.
+fn short_name(description: &str) -> &str {
+    description
+        .split(['-', '(', ' '])
+        .next()
+        .filter(|s| !s.is_empty())
+        .unwrap_or(description)
+}
+
+fn parse_constraints(value: &Value) -> Constraints {
+    let field = |key: &str| value.get(key).and_then(number);
+    match (
+        field("minWidth"),
+        field("maxWidth"),
+        field("minHeight"),
+        field("maxHeight"),
+    ) {
+        (Some(min_width), Some(max_width), Some(min_height), Some(max_height)) => {
+            Constraints::Box {
+                min_width,
+                max_width,
+                min_height,
+                max_height,
+            }
+        }
+        _ => Constraints::Other(str_field(value, "description").unwrap_or("?").to_string()),
+    }
+}
+
+/// Flex settings from the render object's properties, if it is a flex.
+fn parse_flex(render: &Value) -> Option<FlexLayout> {
+    let properties = render.get("properties")?.as_array()?;
+    let property = |name: &str| {
+        properties
+            .iter()
+            .find(|p| str_field(p, "name") == Some(name))
+            .and_then(|p| str_field(p, "description"))
+            .map(String::from)
+    };
+    Some(FlexLayout {
+        direction: property("direction")?,
+        main_axis_alignment: property("mainAxisAlignment"),
+        main_axis_size: property("mainAxisSize"),
+        cross_axis_alignment: property("crossAxisAlignment"),
+    })
+}
+
+fn pair(value: &Value, x: &str, y: &str) -> Option<(f64, f64)> {
+    Some((
+        value.get(x).and_then(number)?,
+        value.get(y).and_then(number)?,
+    ))
+}
+
+/// A number the inspector sent as a string (or, for flex factors, as a number).
+fn number(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(|v| v.as_str())
+}
+
+/// Logical pixels without a needless `.0`, and `infinity` for unbounded.
+fn px(value: f64) -> String {
+    if value.is_infinite() {
+        return "infinity".to_string();
+    }
+    px_number(value)
+}
+
+fn px_number(value: f64) -> String {
+    let rounded = (value * 10.0).round() / 10.0;
+    if rounded.fract() == 0.0 {
+        format!("{rounded:.0}")
+    } else {
+        format!("{rounded:.1}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn child(description: &str, width: &str, flex: Option<i64>) -> Value {
+        let mut child = serde_json::json!({
+            "description": description,
+            "constraints": { "type": "BoxConstraints", "description": "",
+                "minWidth": "0.0", "maxWidth": "Infinity", "minHeight": "0.0", "maxHeight": "48.0" },
+            "size": { "width": width, "height": "20.0" },
+        });
+        if let Some(flex) = flex {
+            child["flexFactor"] = serde_json::json!(flex);
+            child["flexFit"] = serde_json::json!("tight");
+        }
+        child
+    }
+
+    fn overflowing_row() -> Value {
+        serde_json::json!({
+            "description": "Row",
+            "valueId": "inspector-5",
+            "creationLocation": { "file": "file:///app/lib/main.dart", "line": 30, "column": 5 },
+            "renderObject": {
+                "description": "RenderFlex#1a2b3 OVERFLOWING",
+                "properties": [
+                    { "name": "direction", "description": "horizontal" },
+                    { "name": "mainAxisAlignment", "description": "start" },
+                    { "name": "mainAxisSize", "description": "max" },
+                    { "name": "crossAxisAlignment", "description": "center" },
+                ],
+            },
+            "constraints": { "type": "BoxConstraints", "description": "",
+                "minWidth": "390.0", "maxWidth": "390.0", "minHeight": "0.0", "maxHeight": "48.0" },
+            "size": { "width": "390.0", "height": "20.0" },
+            "parentData": { "offsetX": "0.0", "offsetY": "12.0" },
+            "children": [
+                child("Text", "200.0", None),
+                child("SizedBox", "232.0", None),
+            ],
+        })
+    }
+
+    #[test]
+    fn flex_change_arguments() {
+        let current = FlexLayout {
+            direction: "horizontal".into(),
+            main_axis_alignment: Some("start".into()),
+            main_axis_size: Some("max".into()),
+            cross_axis_alignment: Some("center".into()),
+        };
+        let change = FlexChange {
+            main_axis: Some(MainAxisAlignment::SpaceBetween),
+            fit: Some(FlexFit::Loose),
+            ..Default::default()
+        };
+        assert_eq!(change.fit_arg().as_deref(), Some("FlexFit.loose"));
+        assert_eq!(
+            change.alignment_args(Some(&current)),
+            Some((
+                "MainAxisAlignment.spaceBetween".to_string(),
+                "CrossAxisAlignment.center".to_string()
+            ))
+        );
+        assert_eq!(change.alignment_args(None), None);
+    }
+
+    #[test]
+    fn flex_overflow_up_front() {
+        let output = format_layout(&overflowing_row(), false);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "Row  [inspector-5] main.dart:30");
+        assert_eq!(lines[1], "! Row overflowed by 42px horizontally");
+        assert!(lines.contains(&"Constraints: width 390, height 0..48"));
+        assert!(lines.contains(&"Size: 390 x 20"));
+        assert!(lines.contains(&"Offset in parent: (0, 12)"));
+        assert!(
+            lines.contains(&"Flex: horizontal, main axis start, main size max, cross axis center")
+        );
+        assert!(lines.contains(&"  Text  200 x 20  (width 0..infinity, height 0..48)"));
+    }
+
+    #[test]
+    fn diagram_marks_overflow() {
+        let node = LayoutNode::parse(&overflowing_row());
+        let lines = diagram(&node, node.flex.as_ref().unwrap());
+        assert_eq!(lines[0], "Main axis (horizontal):");
+        // 432 px over 60 columns: the Row ends at column 54
+        assert!(lines[1].starts_with("  |Text 200"));
+        assert!(lines[1].contains("|SizedBox 232"));
+        assert!(lines[2].starts_with("  +"));
+        assert!(lines[2].contains(" Row 390 "));
+        assert!(lines[2].ends_with("+!!!!!!"));
+    }
+
+    #[test]
+    fn flex_children_and_box_overflow() {
+        let mut column = overflowing_row();
+        column["renderObject"]["properties"][0]["description"] = serde_json::json!("vertical");
+        column["size"] = serde_json::json!({ "width": "150.0", "height": "48.0" });
+        column["children"] = serde_json::json!([child("Expanded", "100.0", Some(2))]);
+        let node = LayoutNode::parse(&column);
+        assert_eq!(node.children[0].flex_factor, Some(2.0));
+        assert!(node.overflows().is_empty());
+        assert!(format_layout(&column, false).contains("  Expanded  100 x 20  flex 2 (tight)"));
+
+        let stack = serde_json::json!({
+            "description": "Stack",
+            "size": { "width": "100.0", "height": "100.0" },
+            "children": [{
+                "description": "Positioned",
+                "size": { "width": "50.0", "height": "50.0" },
+                "parentData": { "offsetX": "70.5", "offsetY": "-4.0" },
+            }],
+        });
+        assert_eq!(
+            LayoutNode::parse(&stack).overflows(),
+            vec![
+                "Positioned overflows Stack by 4px on the top",
+                "Positioned overflows Stack by 20.5px on the right",
+            ]
+        );
+    }
+}
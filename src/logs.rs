@@ -0,0 +1,284 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::machine::MachineLogEntry;
+use crate::vm_service::{Event, EventData};
+
+/// `package:logging` level values, also used by `developer.log`.
+const LEVELS: &[(&str, i64)] = &[
+    ("SHOUT", 1200),
+    ("SEVERE", 1000),
+    ("WARNING", 900),
+    ("INFO", 800),
+    ("CONFIG", 700),
+    ("FINE", 500),
+    ("FINER", 400),
+    ("FINEST", 300),
+];
+
+const LEVEL_INFO: i64 = 800;
+const LEVEL_SEVERE: i64 = 1000;
+
+/// A single line of app output, from the VM Service or the flutter run machine log.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    /// Milliseconds since the epoch.
+    pub time: Option<i64>,
+    pub level: i64,
+    pub logger: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack_trace: Option<String>,
+}
+
+impl LogEntry {
+    fn line(time: Option<i64>, level: i64, logger: &str, message: &str) -> Self {
+        LogEntry {
+            time,
+            level,
+            logger: logger.to_string(),
+            message: message.to_string(),
+            error: None,
+            stack_trace: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LogFilter {
+    pub min_level: Option<i64>,
+    /// Substring match on the logger name.
+    pub logger: Option<String>,
+    /// Earliest timestamp (ms since epoch) to include.
+    pub since: Option<i64>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(min) = self.min_level
+            && entry.level < min
+        {
+            return false;
+        }
+        if let Some(ref logger) = self.logger
+            && !entry
+                .logger
+                .to_ascii_lowercase()
+                .contains(&logger.to_ascii_lowercase())
+        {
+            return false;
+        }
+        if let Some(since) = self.since
+            && entry.time.is_none_or(|t| t < since)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Parse a level name (`info`, `warning`, ...) or a numeric level.
+pub fn parse_level(level: &str) -> Result<i64> {
+    if let Ok(n) = level.parse::<i64>() {
+        return Ok(n);
+    }
+    LEVELS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(level))
+        .map(|(_, value)| *value)
+        .ok_or_else(|| anyhow!("Unknown log level: {level}"))
+}
+
+/// Parse a relative duration like `30s`, `5m`, `2h` or `1d` into a cutoff timestamp.
+pub fn parse_since(since: &str, now_ms: i64) -> Result<i64> {
+    let since = since.trim();
+    let (number, unit) = match since.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => since.split_at(idx),
+        None => (since, "s"),
+    };
+    let number: i64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration: {since}"))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        "d" => number * 86400,
+        _ => {
+            return Err(anyhow!(
+                "Invalid duration unit in {since} (use s, m, h or d)"
+            ));
+        }
+    };
+    Ok(now_ms - seconds * 1000)
+}
+
+fn level_name(level: i64) -> &'static str {
+    LEVELS
+        .iter()
+        .find(|(_, value)| level >= *value)
+        .map(|(name, _)| *name)
+        .unwrap_or("LOG")
+}
+
+/// Convert a VM Service event into log entries.
+/// Stdout/Stderr writes may carry several lines at once.
+pub fn entries_from_event(event: &Event) -> Vec<LogEntry> {
+    match event.data {
+        EventData::Logging(ref record) => vec![LogEntry {
+            time: event.timestamp,
+            level: record.level,
+            logger: record.logger_name.clone(),
+            message: record.message.clone(),
+            error: record.error.clone(),
+            stack_trace: record.stack_trace.clone(),
+        }],
+        EventData::Stdout(ref text) => text
+            .lines()
+            .map(|l| LogEntry::line(event.timestamp, LEVEL_INFO, "stdout", l))
+            .collect(),
+        EventData::Stderr(ref text) => text
+            .lines()
+            .map(|l| LogEntry::line(event.timestamp, LEVEL_SEVERE, "stderr", l))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Convert an `app.log` machine event into a log entry.
+pub fn entry_from_machine(entry: &MachineLogEntry) -> Option<LogEntry> {
+    if entry.name() != Some("app.log") {
+        return None;
+    }
+    let params = entry.params()?;
+    let message = params.get("log").and_then(|l| l.as_str())?;
+    let is_error = params
+        .get("error")
+        .and_then(|e| e.as_bool())
+        .unwrap_or(false);
+    let level = if is_error { LEVEL_SEVERE } else { LEVEL_INFO };
+    Some(LogEntry {
+        stack_trace: params
+            .get("stackTrace")
+            .and_then(|s| s.as_str())
+            .map(String::from),
+        ..LogEntry::line(Some(entry.time), level, "app", message)
+    })
+}
+
+/// Format a log entry as `HH:MM:SS.mmm LEVEL [logger] message`.
+pub fn format_entry(entry: &LogEntry) -> String {
+    let time = entry
+        .time
+        .map(format_time)
+        .unwrap_or_else(|| " ".repeat(12));
+    let logger = if entry.logger.is_empty() {
+        "log"
+    } else {
+        &entry.logger
+    };
+    let mut out = format!(
+        "{time} {:<7} [{logger}] {}",
+        level_name(entry.level),
+        entry.message
+    );
+    for extra in [&entry.error, &entry.stack_trace].into_iter().flatten() {
+        for line in extra.lines() {
+            out.push_str("\n    ");
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+/// Local wall-clock time of a millisecond timestamp.
+fn format_time(ms: i64) -> String {
+    let secs = ms.div_euclid(1000) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&secs, &mut tm);
+    }
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        ms.rem_euclid(1000)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: i64, logger: &str, time: Option<i64>) -> LogEntry {
+        LogEntry::line(time, level, logger, "message")
+    }
+
+    #[test]
+    fn level_names_and_numbers() {
+        assert_eq!(parse_level("warning").unwrap(), 900);
+        assert_eq!(parse_level("SEVERE").unwrap(), 1000);
+        assert_eq!(parse_level("750").unwrap(), 750);
+        assert!(parse_level("loud").is_err());
+        assert_eq!(level_name(0), "LOG");
+        assert_eq!(level_name(850), "INFO");
+    }
+
+    #[test]
+    fn since_durations() {
+        assert_eq!(parse_since("30s", 100_000).unwrap(), 70_000);
+        assert_eq!(parse_since("2m", 200_000).unwrap(), 80_000);
+        assert_eq!(parse_since("10", 100_000).unwrap(), 90_000);
+        assert!(parse_since("5w", 0).is_err());
+        assert!(parse_since("m", 0).is_err());
+    }
+
+    #[test]
+    fn filter_by_level_logger_and_time() {
+        let filter = LogFilter {
+            min_level: Some(900),
+            logger: Some("Auth".to_string()),
+            since: Some(1000),
+        };
+        assert!(filter.matches(&entry(1000, "app.auth", Some(2000))));
+        assert!(!filter.matches(&entry(800, "app.auth", Some(2000))));
+        assert!(!filter.matches(&entry(1000, "network", Some(2000))));
+        assert!(!filter.matches(&entry(1000, "app.auth", Some(500))));
+        assert!(!filter.matches(&entry(1000, "app.auth", None)));
+        assert!(LogFilter::default().matches(&entry(0, "", None)));
+    }
+
+    #[test]
+    fn machine_app_log() {
+        let entry = MachineLogEntry {
+            time: 42,
+            event: serde_json::json!({
+                "event": "app.log",
+                "params": { "appId": "a", "log": "flutter: hi", "error": true },
+            }),
+        };
+        let log = entry_from_machine(&entry).unwrap();
+        assert_eq!(log.message, "flutter: hi");
+        assert_eq!(log.level, LEVEL_SEVERE);
+        assert_eq!(log.time, Some(42));
+
+        let progress = MachineLogEntry {
+            time: 42,
+            event: serde_json::json!({ "event": "app.progress", "params": {} }),
+        };
+        assert!(entry_from_machine(&progress).is_none());
+    }
+
+    #[test]
+    fn format_without_time() {
+        let mut log = LogEntry::line(None, 900, "", "disk almost full");
+        log.error = Some("IOException".to_string());
+        assert_eq!(
+            format_entry(&log),
+            "             WARNING [log] disk almost full\n    IOException"
+        );
+    }
+}
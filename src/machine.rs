@@ -0,0 +1,147 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// One `flutter run --machine` event, stamped with the time it was read.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MachineLogEntry {
+    /// Milliseconds since the epoch.
+    pub time: i64,
+    pub event: serde_json::Value,
+}
+
+impl MachineLogEntry {
+    pub fn name(&self) -> Option<&str> {
+        self.event.get("event").and_then(|e| e.as_str())
+    }
+
+    pub fn params(&self) -> Option<&serde_json::Value> {
+        self.event.get("params")
+    }
+}
+
+/// Parse one line of `flutter run --machine` stdout.
+/// Events are array-wrapped: `[{"event":"...", "params":{...}}]`.
+pub fn parse_machine_line(line: &str) -> Option<serde_json::Value> {
+    let value = serde_json::from_str::<serde_json::Value>(line.trim()).ok()?;
+    match value {
+        serde_json::Value::Array(mut items) => {
+            if items.is_empty() {
+                None
+            } else {
+                Some(items.swap_remove(0))
+            }
+        }
+        value @ serde_json::Value::Object(_) => Some(value),
+        _ => None,
+    }
+}
+
+/// The result of a response to a machine-protocol request, or its error.
+pub fn response_result(method: &str, response: serde_json::Value) -> Result<serde_json::Value> {
+    if let Some(error) = response.get("error") {
+        let message = error
+            .as_str()
+            .map(String::from)
+            .or_else(|| {
+                error
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| error.to_string());
+        return Err(anyhow!("{method} failed: {message}"));
+    }
+    Ok(response
+        .get("result")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null))
+}
+
+pub fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+/// Follows a machine log file, returning entries as they are appended.
+pub struct MachineLogTail {
+    reader: BufReader<File>,
+    partial: String,
+}
+
+impl MachineLogTail {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            partial: String::new(),
+        })
+    }
+
+    /// Read all complete entries appended since the last call.
+    pub fn read_new(&mut self) -> Vec<MachineLogEntry> {
+        let mut entries = Vec::new();
+        loop {
+            match self.reader.read_line(&mut self.partial) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            // A line without a newline is still being written
+            if !self.partial.ends_with('\n') {
+                break;
+            }
+            if let Ok(entry) = serde_json::from_str::<MachineLogEntry>(&self.partial) {
+                entries.push(entry);
+            }
+            self.partial.clear();
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn parse_array_wrapped_event() {
+        let event = parse_machine_line(
+            r#"[{"event":"app.debugPort","params":{"wsUri":"ws://127.0.0.1:1234/ws"}}]"#,
+        )
+        .unwrap();
+        assert_eq!(event["event"], "app.debugPort");
+        assert_eq!(event["params"]["wsUri"], "ws://127.0.0.1:1234/ws");
+    }
+
+    #[test]
+    fn parse_ignores_plain_text() {
+        assert!(parse_machine_line("Launching lib/main.dart on Linux in debug mode...").is_none());
+        assert!(parse_machine_line("[]").is_none());
+    }
+
+    #[test]
+    fn tail_waits_for_complete_lines() {
+        let path = std::env::temp_dir().join(format!("flutter-cli-tail-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let mut tail = MachineLogTail::open(&path).unwrap();
+        assert!(tail.read_new().is_empty());
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(file, r#"{{"time":1,"event":{{"event":"app.log"}}}}"#).unwrap();
+        assert!(tail.read_new().is_empty());
+
+        writeln!(file).unwrap();
+        let entries = tail.read_new();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name(), Some("app.log"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,14 +1,38 @@
+mod attach;
 mod commands;
 mod config;
+mod debug_flags;
+mod details;
+mod devices;
+mod diff;
+mod eval;
+mod gestures;
+mod golden;
 mod isolate;
+mod layout;
+mod logs;
+mod machine;
+mod mcp;
+mod overrides;
 mod process;
+mod reload;
+mod repl;
+mod screenshot;
+mod session;
 mod snapshot;
 mod state;
+mod supervisor;
 mod vm_service;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::{Parser, Subcommand};
 
+use debug_flags::DebugFlag;
+use golden::CompareOptions;
+use overrides::Override;
+use screenshot::{Dimensions, Mode};
+use session::Session;
+
 #[derive(Parser)]
 #[command(name = "flutter-cli")]
 #[command(about = "Flutter app inspection CLI using Dart VM Service Protocol")]
@@ -25,6 +49,15 @@ struct Cli {
     #[arg(long)]
     project_dir: Option<String>,
 
+    /// Isolate to talk to, by ID, number or name; remembered for later
+    /// commands (`auto` picks the Flutter isolate again)
+    #[arg(long)]
+    isolate: Option<String>,
+
+    /// Device to start the app on, overriding `device` in .flutter-cli.toml
+    #[arg(long)]
+    device: Option<String>,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -42,15 +75,56 @@ enum Command {
         /// Skip framework-internal widgets
         #[arg(short, long)]
         compact: bool,
+        /// Save the complete tree to a file for `diff` instead of printing it
+        #[arg(long)]
+        save: Option<String>,
     },
-    /// Take a screenshot (PNG)
+    /// Compare a saved snapshot with another one or with the live app
+    Diff {
+        /// Snapshot saved with `snapshot --save` (or `--json snapshot` output)
+        before: String,
+        /// Snapshot to compare against, or `live` for the running app
+        #[arg(default_value = "live")]
+        after: String,
+        /// Skip framework-internal widgets
+        #[arg(short, long)]
+        compact: bool,
+    },
+    /// Take a screenshot (PNG, or SKP with --mode skp)
     Screenshot {
         /// Widget valueId to screenshot (whole app if omitted)
         #[arg(long)]
         id: Option<String>,
+        /// What to capture
+        #[arg(long, value_enum, default_value_t = Mode::Widget)]
+        mode: Mode,
         /// Output path
         #[arg(default_value = "/tmp/claude/flutter-screenshot.png")]
         path: String,
+        /// Logical width to fit the widget into (default: its real size)
+        #[arg(long)]
+        width: Option<f64>,
+        /// Logical height to fit the widget into (default: its real size)
+        #[arg(long)]
+        height: Option<f64>,
+        /// Physical pixels per logical pixel (default: the device's)
+        #[arg(long)]
+        pixel_ratio: Option<f64>,
+        /// Compare with a golden PNG, exiting nonzero if they differ
+        #[arg(long)]
+        compare: Option<String>,
+        /// Per-channel difference (0-255) still counted as matching
+        #[arg(long, default_value_t = 0, requires = "compare")]
+        tolerance: u8,
+        /// Percentage of differing pixels still accepted
+        #[arg(long, default_value_t = 0.0, requires = "compare")]
+        threshold: f64,
+        /// Where to write the diff image (default: <path>.diff.png)
+        #[arg(long, requires = "compare")]
+        diff: Option<String>,
+        /// Overwrite the golden with the new screenshot instead of comparing
+        #[arg(long, requires = "compare")]
+        update: bool,
     },
     /// Widget properties
     Details {
@@ -59,52 +133,448 @@ enum Command {
         /// Subtree depth
         #[arg(short, long, default_value_t = 2)]
         depth: usize,
+        /// Also show hidden properties and those at their default value
+        #[arg(short, long)]
+        verbose: bool,
     },
     /// Layout constraints, sizes, flex
     Layout {
         /// Widget valueId from snapshot output
         value_id: String,
+        /// Also list the render object's properties
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Change a flex child's factor or fit, or a flex's alignments, and show the new layout
+    #[command(group(
+        clap::ArgGroup::new("change")
+            .required(true)
+            .multiple(true)
+            .args(["factor", "fit", "main_axis", "cross_axis"])
+    ))]
+    SetFlex {
+        /// Widget valueId from snapshot output
+        value_id: String,
+        /// Flex factor of a Row/Column child (0 makes it inflexible)
+        #[arg(long)]
+        factor: Option<u32>,
+        /// How a Row/Column child fills its space
+        #[arg(long, value_enum)]
+        fit: Option<layout::FlexFit>,
+        /// Main axis alignment of a Row/Column
+        #[arg(long, value_enum)]
+        main_axis: Option<layout::MainAxisAlignment>,
+        /// Cross axis alignment of a Row/Column
+        #[arg(long, value_enum)]
+        cross_axis: Option<layout::CrossAxisAlignment>,
+        /// Also list the render object's properties
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Tap a widget by value ID, semantics label, visible text or key
+    Tap {
+        /// Value ID from snapshot, or text/label/key (prefix with id:, label:, text: or key: to be explicit)
+        target: String,
+    },
+    /// Type into a text field, replacing its text unless --append is given
+    EnterText {
+        /// Field to focus (same forms as tap); defaults to the focused field
+        #[arg(long)]
+        target: Option<String>,
+        /// Text to enter
+        text: String,
+        /// Append to the existing text instead of replacing it
+        #[arg(long)]
+        append: bool,
+        /// Submit afterwards through the field's text input action
+        #[arg(long)]
+        submit: bool,
+    },
+    /// Scroll a scrollable with a synthesized drag (positive --dy scrolls down)
+    Scroll {
+        /// Scrollable, or a widget inside it (same forms as tap)
+        #[arg(long)]
+        target: String,
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        dx: f64,
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        dy: f64,
+    },
+    /// Scroll step by step until a widget is visible
+    ScrollUntilVisible {
+        /// Scrollable, or a widget inside it (same forms as tap)
+        #[arg(long)]
+        target: String,
+        /// Widget to bring into view (same forms as tap)
+        #[arg(long)]
+        find: String,
+        /// Horizontal scroll per step
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        dx: f64,
+        /// Vertical scroll per step
+        #[arg(long, default_value_t = 200.0, allow_negative_numbers = true)]
+        dy: f64,
+        /// Give up after this many steps
+        #[arg(long, default_value_t = 30)]
+        max_scrolls: u32,
+    },
+    /// Drag from a widget's center by an offset
+    Drag {
+        /// Widget to start on (same forms as tap)
+        #[arg(long)]
+        target: String,
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        dx: f64,
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        dy: f64,
+        /// Duration of the drag in milliseconds
+        #[arg(long, default_value_t = 300)]
+        duration: u64,
+    },
+    /// Evaluate a Dart expression in the Flutter isolate
+    Eval {
+        /// Dart expression
+        expression: String,
+        /// Library URI to evaluate in (default: the app's root library)
+        #[arg(long, conflicts_with_all = ["id", "frame"])]
+        library: Option<String>,
+        /// Evaluate against the widget behind a value ID from snapshot (`this` is the widget)
+        #[arg(long, conflicts_with = "frame")]
+        id: Option<String>,
+        /// With --id, evaluate against the widget's State instead
+        #[arg(long, requires = "id")]
+        state: bool,
+        /// Evaluate in a stack frame of the paused isolate (0 is the top frame)
+        #[arg(long)]
+        frame: Option<u32>,
+        /// Levels of fields to expand
+        #[arg(short, long, default_value_t = 1)]
+        depth: usize,
+    },
+    /// List the app's isolates and which one commands use
+    Isolates,
+    /// Make the app render as on another platform, in dark mode, with larger text...
+    Override {
+        #[command(subcommand)]
+        setting: OverrideSetting,
+    },
+    /// Framework debug settings: debug paint, performance overlay, slow animations...
+    Debug {
+        #[command(subcommand)]
+        action: DebugAction,
     },
     /// Render tree (text dump)
     DumpRender,
     /// Semantics tree (text dump)
     DumpSemantics,
-    /// Hot reload
+    /// Hot reload (exits nonzero on failure)
     Reload,
-    /// Hot restart
+    /// Hot restart (exits nonzero on failure)
     Restart,
+    /// Tail app output (print, developer.log, stdout/stderr)
+    Logs {
+        /// Keep streaming new output
+        #[arg(short, long)]
+        follow: bool,
+        /// Only show output newer than this (e.g. 30s, 5m, 2h)
+        #[arg(long)]
+        since: Option<String>,
+        /// Minimum level (finest..shout, or a number)
+        #[arg(short, long)]
+        level: Option<String>,
+        /// Filter by logger name (substring)
+        #[arg(long)]
+        logger: Option<String>,
+    },
+    /// Attach to an app that is already running (e.g. from an IDE)
+    Attach {
+        /// VM Service URI (ws:// or http://); found automatically if omitted
+        uri: Option<String>,
+        /// flutter run output to read the VM Service URI from
+        #[arg(long, conflicts_with = "uri")]
+        log: Option<String>,
+    },
+    /// Connected devices (the one the app starts on is marked with *)
+    Devices,
+    /// List emulators and simulators, or launch one
+    Emulators {
+        /// Emulator ID to launch
+        #[arg(long)]
+        launch: Option<String>,
+        /// Cold boot the launched emulator
+        #[arg(long, requires = "launch")]
+        cold: bool,
+    },
     /// Connection info
     Status,
     /// Kill managed flutter run process
     Stop,
+    /// Run commands interactively over one connection
+    Repl,
+    /// Serve inspector operations as Model Context Protocol tools over stdio
+    Mcp,
+    /// Own the flutter run process in the background (internal)
+    #[command(hide = true)]
+    Supervise {
+        /// Run flutter attach instead of flutter run
+        #[arg(long)]
+        attach: bool,
+        #[arg(long, requires = "attach")]
+        debug_url: Option<String>,
+    },
 }
 
-#[tokio::main]
+// Single-threaded: the WebSocket reader task parses deeply nested inspector
+// JSON and needs the main thread's stack rather than a worker's.
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let project_dir = cli.project_dir.clone();
+    let result = run(Cli::parse()).await;
+    if let Err(ref e) = result
+        && e.is::<commands::AlreadyReported>()
+    {
+        std::process::exit(1);
+    }
+    result
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let project_dir = cli.project_dir;
     let json = cli.json;
 
     match cli.command {
+        Command::Logs {
+            follow,
+            since,
+            level,
+            logger,
+        } => {
+            commands::cmd_logs(
+                project_dir,
+                cli.url,
+                cli.device,
+                follow,
+                since,
+                level,
+                logger,
+                json,
+            )
+            .await
+        }
+        Command::Devices => commands::cmd_devices(project_dir, cli.device, json).await,
+        Command::Emulators { launch, cold } => commands::cmd_emulators(launch, cold, json).await,
+        Command::Status => commands::cmd_status(project_dir, cli.url, json).await,
+        Command::Stop => commands::cmd_stop(project_dir).await,
+        Command::Attach { uri, log } => {
+            commands::cmd_attach(project_dir, cli.device, uri, log, json).await
+        }
+        Command::Supervise { attach, debug_url } => {
+            commands::cmd_supervise(project_dir, cli.device, attach, debug_url).await
+        }
+        Command::Diff {
+            before,
+            after,
+            compact,
+        } if after != "live" => commands::cmd_diff(None, &before, &after, compact, json).await,
+        Command::Repl => {
+            let session = commands::connect(project_dir, cli.url, cli.isolate, cli.device).await?;
+            repl::run(session, json).await
+        }
+        Command::Mcp => commands::cmd_mcp(project_dir, cli.url, cli.isolate, cli.device).await,
+        command => {
+            let mut session =
+                commands::connect(project_dir, cli.url, cli.isolate, cli.device).await?;
+            run_in_session(&mut session, command, json).await?;
+            if !session.temporary_flags.is_empty() {
+                eprintln!("Press Ctrl-C to restore and exit");
+                tokio::signal::ctrl_c().await?;
+                commands::restore_debug_flags(&mut session).await;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Each setting is shown when given without a value.
+#[derive(Subcommand)]
+enum OverrideSetting {
+    /// Platform the framework adapts to (scroll physics, page transitions, fonts)
+    Platform {
+        #[arg(ignore_case = true)]
+        value: Option<overrides::Platform>,
+    },
+    /// Light or dark mode
+    Brightness {
+        #[arg(ignore_case = true)]
+        value: Option<overrides::Brightness>,
+    },
+    /// Text scale factor, as set in the OS accessibility settings
+    TextScale { factor: Option<f64> },
+    /// Locale, as a language tag (e.g. fr-FR)
+    Locale { tag: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum DebugAction {
+    /// Show a setting, or all of them
+    Get {
+        #[arg(ignore_case = true)]
+        flag: Option<DebugFlag>,
+    },
+    /// Change a setting: true/false, or a factor for timeDilation
+    Set {
+        #[arg(ignore_case = true)]
+        flag: DebugFlag,
+        value: String,
+        /// Restore the old value on exit (Ctrl-C, or leaving the REPL)
+        #[arg(long)]
+        temporary: bool,
+    },
+    /// Flip a setting; timeDilation switches between 1 and 5 (slow animations)
+    Toggle {
+        #[arg(ignore_case = true)]
+        flag: DebugFlag,
+        /// Restore the old value on exit (Ctrl-C, or leaving the REPL)
+        #[arg(long)]
+        temporary: bool,
+    },
+}
+
+/// Run a command that talks to the app over an open session.
+async fn run_in_session(session: &mut Session, command: Command, json: bool) -> Result<()> {
+    match command {
         Command::Snapshot {
             depth,
             filter,
             compact,
-        } => commands::cmd_snapshot(project_dir, cli.url, depth, filter, compact, json).await,
-        Command::Screenshot { id, path } => {
-            commands::cmd_screenshot(project_dir, cli.url, id, &path, json).await
+            save,
+        } => commands::cmd_snapshot(session, depth, filter, compact, save, json).await,
+        Command::Diff {
+            before,
+            after,
+            compact,
+        } => {
+            // Comparing two files needs no app, but the REPL routes every command here
+            let session = (after == "live").then_some(session);
+            commands::cmd_diff(session, &before, &after, compact, json).await
         }
-        Command::Details { value_id, depth } => {
-            commands::cmd_details(project_dir, cli.url, &value_id, depth, json).await
+        Command::Screenshot {
+            id,
+            mode,
+            path,
+            width,
+            height,
+            pixel_ratio,
+            compare,
+            tolerance,
+            threshold,
+            diff,
+            update,
+        } => {
+            let compare = compare.map(|golden| CompareOptions {
+                golden,
+                tolerance,
+                threshold,
+                diff_path: diff,
+                update,
+            });
+            let dimensions = Dimensions {
+                width,
+                height,
+                pixel_ratio,
+            };
+            commands::cmd_screenshot(session, id, mode, &path, dimensions, compare, json).await
         }
-        Command::Layout { value_id } => {
-            commands::cmd_layout(project_dir, cli.url, &value_id, json).await
+        Command::Details {
+            value_id,
+            depth,
+            verbose,
+        } => commands::cmd_details(session, &value_id, depth, verbose, json).await,
+        Command::Layout { value_id, verbose } => {
+            commands::cmd_layout(session, &value_id, verbose, json).await
         }
-        Command::DumpRender => commands::cmd_dump_render(project_dir, cli.url, json).await,
-        Command::DumpSemantics => commands::cmd_dump_semantics(project_dir, cli.url, json).await,
-        Command::Reload => commands::cmd_reload(project_dir, cli.url, json).await,
-        Command::Restart => commands::cmd_restart(project_dir, cli.url, json).await,
-        Command::Status => commands::cmd_status(project_dir, cli.url, json).await,
-        Command::Stop => commands::cmd_stop(project_dir).await,
+        Command::SetFlex {
+            value_id,
+            factor,
+            fit,
+            main_axis,
+            cross_axis,
+            verbose,
+        } => {
+            let change = layout::FlexChange {
+                factor,
+                fit,
+                main_axis,
+                cross_axis,
+            };
+            commands::cmd_set_flex(session, &value_id, &change, verbose, json).await
+        }
+        Command::Tap { target } => commands::cmd_tap(session, &target, json).await,
+        Command::EnterText {
+            target,
+            text,
+            append,
+            submit,
+        } => commands::cmd_enter_text(session, target, &text, append, submit, json).await,
+        Command::Scroll { target, dx, dy } => {
+            commands::cmd_scroll(session, &target, dx, dy, json).await
+        }
+        Command::ScrollUntilVisible {
+            target,
+            find,
+            dx,
+            dy,
+            max_scrolls,
+        } => {
+            commands::cmd_scroll_until_visible(session, &target, &find, dx, dy, max_scrolls, json)
+                .await
+        }
+        Command::Drag {
+            target,
+            dx,
+            dy,
+            duration,
+        } => commands::cmd_drag(session, &target, dx, dy, duration, json).await,
+        Command::Eval {
+            expression,
+            library,
+            id,
+            state,
+            frame,
+            depth,
+        } => commands::cmd_eval(session, &expression, library, id, state, frame, depth, json).await,
+        Command::Isolates => commands::cmd_isolates(session, json).await,
+        Command::Override { setting } => {
+            let setting = match setting {
+                OverrideSetting::Platform { value } => Override::Platform(value),
+                OverrideSetting::Brightness { value } => Override::Brightness(value),
+                OverrideSetting::TextScale { factor } => Override::TextScale(factor),
+                OverrideSetting::Locale { tag } => Override::Locale(tag),
+            };
+            commands::cmd_override(session, &setting, json).await
+        }
+        Command::Debug { action } => match action {
+            DebugAction::Get { flag } => commands::cmd_debug_get(session, flag, json).await,
+            DebugAction::Set {
+                flag,
+                value,
+                temporary,
+            } => commands::cmd_debug_set(session, flag, Some(&value), temporary, json).await,
+            DebugAction::Toggle { flag, temporary } => {
+                commands::cmd_debug_set(session, flag, None, temporary, json).await
+            }
+        },
+        Command::DumpRender => commands::cmd_dump_render(session, json).await,
+        Command::DumpSemantics => commands::cmd_dump_semantics(session, json).await,
+        Command::Reload => commands::cmd_reload(session, json).await,
+        Command::Restart => commands::cmd_restart(session, json).await,
+        Command::Logs { .. }
+        | Command::Attach { .. }
+        | Command::Devices
+        | Command::Emulators { .. }
+        | Command::Status
+        | Command::Stop
+        | Command::Repl
+        | Command::Mcp
+        | Command::Supervise { .. } => bail!("This command is not available in the REPL"),
     }
 }
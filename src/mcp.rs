@@ -0,0 +1,568 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::commands;
+use crate::details;
+use crate::layout;
+use crate::screenshot::{Dimensions, Mode};
+use crate::session::Session;
+use crate::snapshot::{self, SnapshotOptions};
+
+/// Protocol revisions we can speak, newest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// JSON-RPC error codes used in responses.
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const PARSE_ERROR: i64 = -32700;
+
+/// Model Context Protocol server over stdio, one JSON-RPC message per line.
+/// The app connection is opened on the first tool call and kept for later ones.
+struct Server {
+    project_dir: PathBuf,
+    url: Option<String>,
+    isolate: Option<String>,
+    device: Option<String>,
+    session: Option<Session>,
+}
+
+/// What a tool call produced, sent back as MCP content.
+enum ToolOutput {
+    Text(String),
+    Image(Vec<u8>),
+    /// A result the tool reports as failed, e.g. a reload with compile errors.
+    Failure(String),
+}
+
+/// Serve MCP requests from stdin until it is closed.
+pub async fn run(
+    project_dir: PathBuf,
+    url: Option<String>,
+    isolate: Option<String>,
+    device: Option<String>,
+) -> Result<()> {
+    let mut server = Server {
+        project_dir,
+        url,
+        isolate,
+        device,
+        session: None,
+    };
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(message) => server.handle(message).await,
+            Err(e) => Some(error_response(
+                serde_json::Value::Null,
+                PARSE_ERROR,
+                &format!("Invalid JSON: {e}"),
+            )),
+        };
+        if let Some(response) = response {
+            stdout.write_all(format!("{response}\n").as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    /// Answer a request. Notifications (no id) get no response.
+    async fn handle(&mut self, message: serde_json::Value) -> Option<serde_json::Value> {
+        let id = message.get("id").cloned()?;
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = message
+            .get("params")
+            .cloned()
+            .unwrap_or(serde_json::json!({}));
+
+        let result = match method {
+            "initialize" => Ok(initialize_result(&params)),
+            "ping" => Ok(serde_json::json!({})),
+            "tools/list" => Ok(serde_json::json!({ "tools": tool_definitions() })),
+            "tools/call" => self.call_tool(&params).await,
+            _ => {
+                return Some(error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("Unknown method: {method}"),
+                ));
+            }
+        };
+        Some(match result {
+            Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => error_response(id, INVALID_PARAMS, &format!("{e:#}")),
+        })
+    }
+
+    /// Run a tool. Failures talking to the app become error results the
+    /// model can read rather than protocol errors.
+    async fn call_tool(&mut self, params: &serde_json::Value) -> Result<serde_json::Value> {
+        let name = params
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or_else(|| anyhow!("Missing tool name"))?;
+        if !TOOLS.iter().any(|tool| tool.name == name) {
+            return Err(anyhow!("Unknown tool: {name}"));
+        }
+        let arguments = params
+            .get("arguments")
+            .cloned()
+            .unwrap_or(serde_json::json!({}));
+
+        // Check the arguments before starting or attaching to the app for them
+        let call = match ToolCall::parse(name, arguments) {
+            Ok(call) => call,
+            Err(e) => return Ok(ToolOutput::Failure(format!("Error: {e:#}")).into_result()),
+        };
+        let output = match self.run_tool(call).await {
+            Ok(output) => output,
+            Err(e) => {
+                // The app may have gone away; reconnect on the next call
+                if let Some(ref mut session) = self.session
+                    && !session.conn.ping().await
+                {
+                    self.session = None;
+                }
+                ToolOutput::Failure(format!("Error: {e:#}"))
+            }
+        };
+        Ok(output.into_result())
+    }
+
+    async fn run_tool(&mut self, call: ToolCall) -> Result<ToolOutput> {
+        let session = self.session().await?;
+        match call {
+            ToolCall::Snapshot(args) => {
+                let isolate_id = session.isolate_id().await?;
+                let tree = snapshot::get_widget_tree(&mut session.conn, &isolate_id).await?;
+                let opts = SnapshotOptions {
+                    max_depth: args.depth,
+                    filter: args.filter,
+                    compact: args.compact,
+                };
+                let output = snapshot::format_tree(&tree, &opts);
+                Ok(ToolOutput::Text(if output.is_empty() {
+                    "(empty widget tree)".to_string()
+                } else {
+                    output
+                }))
+            }
+            ToolCall::Details(args) => {
+                let result = commands::fetch_details(session, &args.value_id, args.depth).await?;
+                Ok(ToolOutput::Text(details::format_details(
+                    &result,
+                    args.verbose,
+                )))
+            }
+            ToolCall::Layout(args) => {
+                let result = commands::fetch_layout(session, &args.value_id).await?;
+                Ok(ToolOutput::Text(layout::format_layout(&result, false)))
+            }
+            ToolCall::Screenshot(args) => {
+                let dimensions = Dimensions {
+                    width: args.width,
+                    height: args.height,
+                    pixel_ratio: args.pixel_ratio,
+                };
+                let bytes =
+                    commands::take_screenshot(session, args.mode, args.id.as_deref(), dimensions)
+                        .await?;
+                Ok(ToolOutput::Image(bytes))
+            }
+            ToolCall::DumpRender => Ok(ToolOutput::Text(commands::dump_render(session).await?)),
+            ToolCall::DumpSemantics => {
+                Ok(ToolOutput::Text(commands::dump_semantics(session).await?))
+            }
+            ToolCall::Reload | ToolCall::Restart => {
+                let report = if matches!(call, ToolCall::Reload) {
+                    commands::hot_reload(session).await?
+                } else {
+                    commands::hot_restart(session).await?
+                };
+                Ok(if report.succeeded() {
+                    ToolOutput::Text(report.format())
+                } else {
+                    ToolOutput::Failure(report.format())
+                })
+            }
+        }
+    }
+
+    /// The open session, connecting (and starting flutter run) on first use.
+    async fn session(&mut self) -> Result<&mut Session> {
+        if self.session.is_none() {
+            let mut session = Session::connect(
+                self.project_dir.clone(),
+                self.url.clone(),
+                self.isolate.clone(),
+                self.device.clone(),
+            )
+            .await?;
+            session.watch_isolate_exits().await?;
+            self.session = Some(session);
+        }
+        Ok(self.session.as_mut().unwrap())
+    }
+}
+
+impl ToolOutput {
+    fn into_result(self) -> serde_json::Value {
+        let (content, is_error) = match self {
+            ToolOutput::Text(text) => (serde_json::json!({ "type": "text", "text": text }), false),
+            ToolOutput::Image(bytes) => {
+                use base64::Engine;
+                let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+                (
+                    serde_json::json!({ "type": "image", "data": data, "mimeType": "image/png" }),
+                    false,
+                )
+            }
+            ToolOutput::Failure(text) => {
+                (serde_json::json!({ "type": "text", "text": text }), true)
+            }
+        };
+        serde_json::json!({ "content": [content], "isError": is_error })
+    }
+}
+
+fn initialize_result(params: &serde_json::Value) -> serde_json::Value {
+    // Answer with the client's revision when we know it, else our newest
+    let requested = params.get("protocolVersion").and_then(|v| v.as_str());
+    let version = requested
+        .and_then(|v| PROTOCOL_VERSIONS.iter().find(|&&known| known == v))
+        .unwrap_or(&PROTOCOL_VERSIONS[0]);
+    serde_json::json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+    })
+}
+
+fn error_response(id: serde_json::Value, code: i64, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// A tool call with its arguments checked.
+enum ToolCall {
+    Snapshot(SnapshotArgs),
+    Details(DetailsArgs),
+    Layout(ValueIdArgs),
+    Screenshot(ScreenshotArgs),
+    DumpRender,
+    DumpSemantics,
+    Reload,
+    Restart,
+}
+
+impl ToolCall {
+    fn parse(name: &str, arguments: serde_json::Value) -> Result<Self> {
+        Ok(match name {
+            "snapshot" => ToolCall::Snapshot(parse_arguments(arguments)?),
+            "details" => ToolCall::Details(parse_arguments(arguments)?),
+            "layout" => ToolCall::Layout(parse_arguments(arguments)?),
+            "screenshot" => {
+                let args: ScreenshotArgs = parse_arguments(arguments)?;
+                // Tool results carry PNG images; a Skia picture is not one
+                if args.mode == Mode::Skp {
+                    return Err(anyhow!(
+                        "mode must be widget or window; skp captures are only available from \
+                         the command line"
+                    ));
+                }
+                ToolCall::Screenshot(args)
+            }
+            "dump_render" => ToolCall::DumpRender,
+            "dump_semantics" => ToolCall::DumpSemantics,
+            "reload" => ToolCall::Reload,
+            "restart" => ToolCall::Restart,
+            _ => return Err(anyhow!("Unknown tool: {name}")),
+        })
+    }
+}
+
+fn parse_arguments<T: DeserializeOwned>(arguments: serde_json::Value) -> Result<T> {
+    serde_json::from_value(arguments).map_err(|e| anyhow!("Invalid arguments: {e}"))
+}
+
+#[derive(Deserialize)]
+struct SnapshotArgs {
+    depth: Option<usize>,
+    filter: Option<String>,
+    #[serde(default)]
+    compact: bool,
+}
+
+#[derive(Deserialize)]
+struct DetailsArgs {
+    value_id: String,
+    #[serde(default = "default_details_depth")]
+    depth: usize,
+    #[serde(default)]
+    verbose: bool,
+}
+
+fn default_details_depth() -> usize {
+    2
+}
+
+#[derive(Deserialize)]
+struct ValueIdArgs {
+    value_id: String,
+}
+
+#[derive(Deserialize)]
+struct ScreenshotArgs {
+    id: Option<String>,
+    #[serde(default)]
+    mode: Mode,
+    width: Option<f64>,
+    height: Option<f64>,
+    pixel_ratio: Option<f64>,
+}
+
+struct Tool {
+    name: &'static str,
+    description: &'static str,
+    /// JSON schema properties of the arguments, and which are required.
+    properties: fn() -> serde_json::Value,
+    required: &'static [&'static str],
+}
+
+const TOOLS: &[Tool] = &[
+    Tool {
+        name: "snapshot",
+        description: "Widget tree as indented text: widget type, value ID and source location",
+        properties: || {
+            serde_json::json!({
+                "depth": { "type": "integer", "minimum": 0, "description": "Maximum tree depth" },
+                "filter": { "type": "string", "description": "Filter by widget name (substring or glob with *)" },
+                "compact": { "type": "boolean", "description": "Skip framework-internal widgets" },
+            })
+        },
+        required: &[],
+    },
+    Tool {
+        name: "details",
+        description: "Properties of a widget, by value ID from snapshot",
+        properties: || {
+            serde_json::json!({
+                "value_id": { "type": "string", "description": "Widget value ID from snapshot" },
+                "depth": { "type": "integer", "minimum": 0, "description": "Subtree depth (default 2)" },
+                "verbose": { "type": "boolean", "description": "Also show hidden and default-valued properties" },
+            })
+        },
+        required: &["value_id"],
+    },
+    Tool {
+        name: "layout",
+        description: "Layout constraints, sizes and flex properties of a widget",
+        properties: || {
+            serde_json::json!({
+                "value_id": { "type": "string", "description": "Widget value ID from snapshot" },
+            })
+        },
+        required: &["value_id"],
+    },
+    Tool {
+        name: "screenshot",
+        description: "PNG screenshot of the app or of one widget",
+        properties: || {
+            serde_json::json!({
+                "id": { "type": "string", "description": "Widget value ID to screenshot (whole app if omitted)" },
+                "mode": { "type": "string", "enum": ["widget", "window"], "description": "widget: the widget subtree (default); window: the whole frame including overlays and platform views" },
+                "width": { "type": "number", "description": "Logical width to fit the widget into (default: its real size)" },
+                "height": { "type": "number", "description": "Logical height to fit the widget into (default: its real size)" },
+                "pixel_ratio": { "type": "number", "description": "Physical pixels per logical pixel (default: the device's)" },
+            })
+        },
+        required: &[],
+    },
+    Tool {
+        name: "dump_render",
+        description: "Render tree (text dump)",
+        properties: || serde_json::json!({}),
+        required: &[],
+    },
+    Tool {
+        name: "dump_semantics",
+        description: "Semantics tree in traversal order (text dump)",
+        properties: || serde_json::json!({}),
+        required: &[],
+    },
+    Tool {
+        name: "reload",
+        description: "Hot reload; reports compile errors and fails when the reload did",
+        properties: || serde_json::json!({}),
+        required: &[],
+    },
+    Tool {
+        name: "restart",
+        description: "Hot restart (requires a managed flutter run process)",
+        properties: || serde_json::json!({}),
+        required: &[],
+    },
+];
+
+fn tool_definitions() -> Vec<serde_json::Value> {
+    TOOLS
+        .iter()
+        .map(|tool| {
+            serde_json::json!({
+                "name": tool.name,
+                "description": tool.description,
+                "inputSchema": {
+                    "type": "object",
+                    "properties": (tool.properties)(),
+                    "required": tool.required,
+                },
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        Server {
+            project_dir: PathBuf::from("/nonexistent"),
+            url: None,
+            isolate: None,
+            device: None,
+            session: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn initialize_negotiates_version() {
+        let response = server()
+            .handle(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": { "protocolVersion": "2024-11-05", "capabilities": {} },
+            }))
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+        assert!(response["result"]["capabilities"]["tools"].is_object());
+
+        let response = server()
+            .handle(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "initialize",
+                "params": { "protocolVersion": "1999-01-01" },
+            }))
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["protocolVersion"], PROTOCOL_VERSIONS[0]);
+    }
+
+    #[tokio::test]
+    async fn notifications_and_unknown_methods() {
+        let mut server = server();
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized",
+        });
+        assert!(server.handle(notification).await.is_none());
+
+        let response = server
+            .handle(serde_json::json!({ "jsonrpc": "2.0", "id": "a", "method": "resources/list" }))
+            .await
+            .unwrap();
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn tools_have_object_schemas() {
+        let response = server()
+            .handle(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+            .await
+            .unwrap();
+        let tools = response["result"]["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), TOOLS.len());
+        let details = tools.iter().find(|t| t["name"] == "details").unwrap();
+        assert_eq!(details["inputSchema"]["type"], "object");
+        assert_eq!(
+            details["inputSchema"]["required"],
+            serde_json::json!(["value_id"])
+        );
+        assert!(details["inputSchema"]["properties"]["depth"].is_object());
+    }
+
+    #[test]
+    fn tool_output_content() {
+        let image = ToolOutput::Image(vec![0x89, b'P', b'N', b'G']).into_result();
+        assert_eq!(image["content"][0]["type"], "image");
+        assert_eq!(image["content"][0]["mimeType"], "image/png");
+        assert_eq!(image["content"][0]["data"], "iVBORw==");
+        assert_eq!(image["isError"], false);
+
+        let failure = ToolOutput::Failure("Hot reload failed".to_string()).into_result();
+        assert_eq!(failure["content"][0]["text"], "Hot reload failed");
+        assert_eq!(failure["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn invalid_arguments_fail_without_connecting() {
+        let mut server = server();
+        let response = server
+            .handle(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": "screenshot", "arguments": { "mode": "skp" } },
+            }))
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["isError"], true);
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("skp"), "{text}");
+        assert!(server.session.is_none());
+
+        let response = server
+            .handle(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "tools/call",
+                "params": { "name": "details", "arguments": { "depth": 3 } },
+            }))
+            .await
+            .unwrap();
+        assert!(
+            response["result"]["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("Invalid arguments")
+        );
+        assert!(server.session.is_none());
+    }
+
+    #[test]
+    fn details_arguments_default_depth() {
+        let args: DetailsArgs =
+            parse_arguments(serde_json::json!({ "value_id": "inspector-4" })).unwrap();
+        assert_eq!(args.depth, 2);
+        assert!(parse_arguments::<DetailsArgs>(serde_json::json!({})).is_err());
+    }
+}
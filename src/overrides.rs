@@ -0,0 +1,224 @@
+use anyhow::{Context, Result, bail};
+
+use crate::eval;
+use crate::vm_service::VmServiceConnection;
+
+/// Text scale and locale have no service extension; they are fed to the app
+/// through the engine's hooks in `dart:ui`, as if the OS had changed them.
+const DART_UI: &str = "dart:ui";
+
+/// Value of `ext.flutter.platformOverride`, named as Flutter's `TargetPlatform`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Android,
+    #[value(name = "iOS")]
+    Ios,
+    Fuchsia,
+    Linux,
+    #[value(name = "macOS")]
+    MacOs,
+    Windows,
+    /// The platform the app runs on
+    Default,
+}
+
+impl Platform {
+    /// The value `ext.flutter.platformOverride` takes.
+    fn dart_name(self) -> &'static str {
+        match self {
+            Platform::Android => "android",
+            Platform::Ios => "iOS",
+            Platform::Fuchsia => "fuchsia",
+            Platform::Linux => "linux",
+            Platform::MacOs => "macOS",
+            Platform::Windows => "windows",
+            Platform::Default => "default",
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Brightness {
+    Light,
+    Dark,
+    /// The platform's brightness
+    Default,
+}
+
+/// A setting `override` changes or, without a value, reads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Override {
+    Platform(Option<Platform>),
+    Brightness(Option<Brightness>),
+    TextScale(Option<f64>),
+    Locale(Option<String>),
+}
+
+impl Override {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Override::Platform(_) => "platform",
+            Override::Brightness(_) => "brightness",
+            Override::TextScale(_) => "textScale",
+            Override::Locale(_) => "locale",
+        }
+    }
+
+    /// Apply the override, returning the value now in effect.
+    pub async fn apply(&self, conn: &mut VmServiceConnection, isolate_id: &str) -> Result<String> {
+        match self {
+            Override::Platform(value) => platform(conn, isolate_id, *value).await,
+            Override::Brightness(value) => brightness(conn, isolate_id, *value).await,
+            Override::TextScale(factor) => text_scale(conn, isolate_id, *factor).await,
+            Override::Locale(tag) => locale(conn, isolate_id, tag.as_deref()).await,
+        }
+    }
+}
+
+/// Override the platform the framework adapts to (scrolling physics, page
+/// transitions, typography...), or with None just read it. Returns the
+/// platform in effect.
+async fn platform(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    value: Option<Platform>,
+) -> Result<String> {
+    let mut params = serde_json::json!({ "isolateId": isolate_id });
+    if let Some(value) = value {
+        params["value"] = serde_json::json!(value.dart_name());
+    }
+    extension_value(conn, "ext.flutter.platformOverride", params).await
+}
+
+/// Override the platform brightness, or with None just read it. Returns the
+/// brightness in effect.
+async fn brightness(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    value: Option<Brightness>,
+) -> Result<String> {
+    let mut params = serde_json::json!({ "isolateId": isolate_id });
+    if let Some(value) = value {
+        // Anything but a Brightness value clears the override
+        let value = match value {
+            Brightness::Light => "Brightness.light",
+            Brightness::Dark => "Brightness.dark",
+            Brightness::Default => "default",
+        };
+        params["value"] = serde_json::json!(value);
+    }
+    let value = extension_value(conn, "ext.flutter.brightnessOverride", params).await?;
+    Ok(value
+        .strip_prefix("Brightness.")
+        .map(String::from)
+        .unwrap_or(value))
+}
+
+/// Set the text scale factor the app sees, or with None just read it.
+async fn text_scale(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    factor: Option<f64>,
+) -> Result<String> {
+    let read = "'${PlatformDispatcher.instance.textScaleFactor}'";
+    let expression = match factor {
+        Some(factor) if factor > 0.0 => {
+            // The hook takes all user settings; keep the others as they are
+            let settings = format!(
+                "<String, Object?>{{'textScaleFactor': {factor:?}, \
+                 'alwaysUse24HourFormat': PlatformDispatcher.instance.alwaysUse24HourFormat, \
+                 'platformBrightness': PlatformDispatcher.instance.platformBrightness.name, \
+                 'systemFontFamily': PlatformDispatcher.instance.systemFontFamily}}"
+            );
+            format!("() {{ _updateUserSettingsData(json.encode({settings})); return {read}; }}()")
+        }
+        Some(_) => bail!("The text scale factor must be positive, e.g. 1.5"),
+        None => read.to_string(),
+    };
+    evaluate_in_dart_ui(conn, isolate_id, &expression).await
+}
+
+/// Set the locale the app sees, or with None just read it. Returns the
+/// locale as a language tag.
+async fn locale(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    tag: Option<&str>,
+) -> Result<String> {
+    let read = "PlatformDispatcher.instance.locale.toLanguageTag()";
+    let expression = match tag {
+        Some(tag) => {
+            let subtags = locale_subtags(tag)?
+                .map(|s| eval::dart_string(&s))
+                .join(", ");
+            format!("() {{ _updateLocales(<String>[{subtags}]); return {read}; }}()")
+        }
+        None => read.to_string(),
+    };
+    evaluate_in_dart_ui(conn, isolate_id, &expression).await
+}
+
+/// Split a language tag like `fr-FR` or `zh_Hant_TW` into the language,
+/// country, script and variant codes `_updateLocales` takes per locale.
+fn locale_subtags(tag: &str) -> Result<[String; 4]> {
+    let mut parts = tag.split(['-', '_']);
+    let language = parts.next().unwrap_or_default();
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        bail!("Not a locale: {tag} (expected e.g. en, fr-FR or zh-Hant-TW)");
+    }
+    let (mut country, mut script) = (String::new(), String::new());
+    for part in parts {
+        match part.len() {
+            4 if part.chars().all(|c| c.is_ascii_alphabetic()) => {
+                script = format!("{}{}", part[..1].to_uppercase(), part[1..].to_lowercase());
+            }
+            2 | 3 => country = part.to_uppercase(),
+            _ => bail!("Not a locale: {tag} (expected e.g. en, fr-FR or zh-Hant-TW)"),
+        }
+    }
+    Ok([language.to_lowercase(), country, script, String::new()])
+}
+
+/// Call a framework service extension and return its `value`.
+async fn extension_value(
+    conn: &mut VmServiceConnection,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<String> {
+    let response = conn
+        .send(method, params)
+        .await
+        .with_context(|| format!("{method} failed; is the app running in debug mode?"))?;
+    response
+        .get("value")
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .with_context(|| format!("Unexpected {method} response: {response}"))
+}
+
+async fn evaluate_in_dart_ui(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    expression: &str,
+) -> Result<String> {
+    let library = eval::library_id(conn, isolate_id, DART_UI).await?;
+    eval::evaluate_string(conn, isolate_id, &library, expression).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_tags() {
+        assert_eq!(locale_subtags("fr-FR").unwrap(), ["fr", "FR", "", ""]);
+        assert_eq!(locale_subtags("en").unwrap(), ["en", "", "", ""]);
+        assert_eq!(
+            locale_subtags("zh_hant_tw").unwrap(),
+            ["zh", "TW", "Hant", ""]
+        );
+        assert_eq!(locale_subtags("es-419").unwrap(), ["es", "419", "", ""]);
+        assert!(locale_subtags("french").is_err());
+        assert!(locale_subtags("fr-FRANCE1").is_err());
+    }
+}
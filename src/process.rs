@@ -1,18 +1,22 @@
 use anyhow::{Result, anyhow};
-use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::process::{Command, Stdio};
-use std::time::SystemTime;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
 
 use crate::config::Config;
-use crate::state::State;
+use crate::state::{self, State};
+use crate::supervisor;
 use crate::vm_service::{self, VmServiceConnection};
 
 /// Ensure a connection to the Flutter app's VM Service.
-/// If --url is provided, connect directly. Otherwise, use process management.
+/// If --url is provided, connect directly. Otherwise, use process management,
+/// starting the app on `device` (overriding the configured one) if needed.
 pub async fn ensure_connection(
     project_dir: &Path,
     url: Option<&str>,
+    device: Option<&str>,
 ) -> Result<VmServiceConnection> {
     if let Some(url) = url {
         return VmServiceConnection::connect(url).await;
@@ -21,6 +25,14 @@ pub async fn ensure_connection(
     // Try existing state
     if let Some(state) = State::load(project_dir)? {
         if state.is_pid_alive() {
+            if let Some(device) = device
+                && !state.runs_on(device)
+            {
+                return Err(anyhow!(
+                    "The managed app runs on {}; run `stop` first to start it on {device}",
+                    state.device.as_deref().unwrap_or("another device")
+                ));
+            }
             if let Ok(mut conn) = vm_service::try_connect(&state.ws_uri, 3000).await
                 && conn.ping().await
             {
@@ -28,159 +40,119 @@ pub async fn ensure_connection(
             }
             // Connection failed, kill the old process
             eprintln!("VM Service unreachable, restarting flutter run...");
-            kill_process(state.pid);
+            terminate(project_dir, &state).await;
         }
         State::remove(project_dir)?;
     }
 
     // Start a new flutter run process
-    start_flutter_run(project_dir).await
+    start_flutter_run(project_dir, device).await
 }
 
-async fn start_flutter_run(project_dir: &Path) -> Result<VmServiceConnection> {
-    let config = Config::load(project_dir)?;
-    let args = config.flutter_run_args();
+async fn start_flutter_run(
+    project_dir: &Path,
+    device: Option<&str>,
+) -> Result<VmServiceConnection> {
+    let config = Config::load(project_dir)?.with_device(device.map(String::from));
+    if config.daemon {
+        eprintln!("Starting: flutter daemon");
+    } else {
+        eprintln!("Starting: flutter {}", config.flutter_run_args().join(" "));
+    }
+    let ws_uri = start_supervisor(project_dir, device, &[]).await?;
+    VmServiceConnection::connect(&ws_uri).await
+}
+
+/// Attach to an app that is already running: a supervisor owns `flutter attach
+/// --machine` like it would `flutter run`, so later commands, reloads and
+/// restarts go through it. Returns the VM Service URI.
+pub async fn attach(
+    project_dir: &Path,
+    device: Option<&str>,
+    debug_url: Option<&str>,
+) -> Result<String> {
+    if let Some(state) = State::load(project_dir)? {
+        if state.is_pid_alive() {
+            return Err(anyhow!(
+                "Already managing flutter {} (PID {}); run `stop` first",
+                state.args.first().map_or("run", String::as_str),
+                state.pid
+            ));
+        }
+        State::remove(project_dir)?;
+    }
 
-    eprintln!("Starting: flutter {}", args.join(" "));
+    let config = Config::load(project_dir)?.with_device(device.map(String::from));
+    eprintln!(
+        "Starting: flutter {}",
+        config.flutter_attach_args(debug_url).join(" ")
+    );
+    let mut supervise_args = vec!["--attach"];
+    if let Some(url) = debug_url {
+        supervise_args.extend(["--debug-url", url]);
+    }
+    start_supervisor(project_dir, device, &supervise_args).await
+}
 
+/// Spawn the supervisor and wait for it to report the VM Service URI.
+async fn start_supervisor(
+    project_dir: &Path,
+    device: Option<&str>,
+    supervise_args: &[&str],
+) -> Result<String> {
     let stderr_path = stderr_log_path(project_dir);
     std::fs::create_dir_all(stderr_path.parent().unwrap())?;
     let stderr_file = std::fs::File::create(&stderr_path)?;
 
-    let mut child = Command::new("flutter")
-        .args(&args)
+    // The supervisor owns flutter's stdin/stdout and outlives this invocation.
+    // Its own process group keeps terminal signals meant for us away from it.
+    let mut command = Command::new(std::env::current_exe()?);
+    command.arg("--project-dir").arg(project_dir);
+    if let Some(device) = device {
+        command.arg("--device").arg(device);
+    }
+    let mut child = command
+        .arg("supervise")
+        .args(supervise_args)
         .current_dir(project_dir)
-        .stdin(Stdio::piped())
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(stderr_file)
+        .process_group(0)
         .spawn()
-        .map_err(|e| anyhow!("Failed to start flutter: {e}"))?;
+        .map_err(|e| anyhow!("Failed to start supervisor: {e}"))?;
 
-    let pid = child.id();
+    // The supervisor reports startup on its first stdout line
     let stdout = child.stdout.take().unwrap();
-
-    // Parse stdout for app.debugPort event (blocking in a thread)
-    let project_dir_owned = project_dir.to_path_buf();
-    let args_clone = args.clone();
-    let result = tokio::task::spawn_blocking(move || {
-        parse_flutter_machine_output(stdout, pid, &project_dir_owned, &args_clone)
-    })
-    .await??;
-
-    let (ws_uri, app_id) = result;
-
-    // Save state
-    let state = State {
-        pid,
-        ws_uri: ws_uri.clone(),
-        app_id,
-        cwd: project_dir.to_string_lossy().to_string(),
-        args,
-        started_at: SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
-    };
-    state.save(project_dir)?;
-
-    let conn = VmServiceConnection::connect(&ws_uri).await?;
-    Ok(conn)
-}
-
-fn parse_flutter_machine_output(
-    stdout: std::process::ChildStdout,
-    pid: u32,
-    project_dir: &Path,
-    _args: &[String],
-) -> Result<(String, Option<String>)> {
-    let reader = BufReader::new(stdout);
-    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(120);
-
-    let mut ws_uri: Option<String> = None;
-    let mut app_id: Option<String> = None;
-
-    for line in reader.lines() {
-        if std::time::Instant::now() > deadline {
-            kill_process(pid);
-            State::remove(project_dir).ok();
-            return Err(anyhow!("Timeout waiting for flutter run to start (120s)"));
-        }
-
-        let line = match line {
-            Ok(l) => l,
-            Err(e) => {
-                return Err(anyhow!("Error reading flutter stdout: {e}"));
-            }
-        };
-
-        // flutter run --machine outputs JSON events, one per line
-        let Ok(event) = serde_json::from_str::<serde_json::Value>(&line) else {
-            continue;
-        };
-
-        // Look for array-wrapped events: [{"event":"...", "params":{...}}]
-        let event = if event.is_array() {
-            match event.as_array().and_then(|a| a.first()) {
-                Some(e) => e.clone(),
-                None => continue,
-            }
-        } else {
-            event
-        };
-
-        let event_name = event.get("event").and_then(|e| e.as_str());
-
-        match event_name {
-            Some("app.debugPort") => {
-                if let Some(params) = event.get("params") {
-                    if let Some(uri) = params.get("wsUri").and_then(|u| u.as_str()) {
-                        ws_uri = Some(uri.to_string());
-                    }
-                    if let Some(id) = params.get("appId").and_then(|a| a.as_str()) {
-                        app_id = Some(id.to_string());
-                    }
-                }
-            }
-            Some("app.started") => {
-                // App is ready, we should have wsUri by now
-                if ws_uri.is_some() {
-                    break;
-                }
-            }
-            Some("app.stop") | Some("daemon.shutdown") => {
-                State::remove(project_dir).ok();
-                return Err(anyhow!("Flutter app exited during startup"));
-            }
-            _ => {}
-        }
-
-        // If we have the wsUri, we can connect even before app.started
-        if ws_uri.is_some() {
-            break;
-        }
+    let mut lines = BufReader::new(stdout).lines();
+    let line = tokio::time::timeout(Duration::from_secs(130), lines.next_line())
+        .await
+        .map_err(|_| anyhow!("Timeout waiting for flutter to start"))??
+        .ok_or_else(|| {
+            anyhow!(
+                "Supervisor exited during startup. Check {}",
+                stderr_path.display()
+            )
+        })?;
+
+    let startup: serde_json::Value = serde_json::from_str(&line)?;
+    if let Some(error) = startup.get("error").and_then(|e| e.as_str()) {
+        return Err(anyhow!("{error}"));
     }
+    startup
+        .get("ws_uri")
+        .and_then(|u| u.as_str())
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Supervisor did not report a VM Service URI"))
+}
 
-    match ws_uri {
-        Some(uri) => Ok((uri, app_id)),
-        None => {
-            kill_process(pid);
-            State::remove(project_dir).ok();
-            Err(anyhow!(
-                "flutter run exited without providing VM Service URI. Check {}",
-                stderr_log_path(project_dir).display()
-            ))
-        }
-    }
+pub fn stderr_log_path(project_dir: &Path) -> std::path::PathBuf {
+    state::runtime_file_path(project_dir, "stderr")
 }
 
-fn stderr_log_path(project_dir: &Path) -> std::path::PathBuf {
-    use sha2::{Digest, Sha256};
-    let mut hasher = Sha256::new();
-    hasher.update(project_dir.to_string_lossy().as_bytes());
-    let hash = hasher.finalize();
-    let hex = format!("{:x}", hash);
-    let short = &hex[..16];
-    std::path::PathBuf::from("/tmp/claude/flutter-cli").join(format!("{short}.stderr"))
+/// Machine events recorded from the managed flutter run's stdout.
+pub fn machine_log_path(project_dir: &Path) -> std::path::PathBuf {
+    state::runtime_file_path(project_dir, "machine.log")
 }
 
 fn kill_process(pid: u32) {
@@ -194,14 +166,46 @@ fn kill_process(pid: u32) {
     }
 }
 
+/// Ask the supervisor to shut down flutter run, killing both directly if it doesn't answer.
+async fn terminate(project_dir: &Path, state: &State) {
+    if supervisor::request(project_dir, "supervisor.stop", serde_json::json!({}))
+        .await
+        .is_ok()
+    {
+        // The supervisor removes the state file once flutter run is gone
+        for _ in 0..20 {
+            if !state.is_pid_alive() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+    kill_process(state.pid);
+    if let Some(pid) = state.supervisor_pid {
+        kill_process(pid);
+    }
+}
+
 /// Stop the managed flutter run process for this project directory.
-pub fn stop_process(project_dir: &Path) -> Result<()> {
+pub async fn stop_process(project_dir: &Path) -> Result<()> {
     let state = State::load(project_dir)?;
     match state {
         Some(s) => {
             if s.is_pid_alive() {
-                kill_process(s.pid);
-                eprintln!("Stopped flutter run (PID {})", s.pid);
+                terminate(project_dir, &s).await;
+                if s.args.first().is_some_and(|a| a == "attach") {
+                    // Ending flutter attach leaves the app it attached to running
+                    eprintln!(
+                        "Detached from the app (flutter attach PID {}); the app keeps running",
+                        s.pid
+                    );
+                } else {
+                    eprintln!(
+                        "Stopped flutter {} (PID {})",
+                        s.args.first().map_or("run", String::as_str),
+                        s.pid
+                    );
+                }
             } else {
                 eprintln!("Process already dead (PID {})", s.pid);
             }
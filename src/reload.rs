@@ -0,0 +1,239 @@
+use serde::Serialize;
+
+/// How an `app.restart` request ended.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReloadOutcome {
+    Success,
+    CompileError,
+    RestartRequired,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReloadReport {
+    pub action: &'static str,
+    pub outcome: ReloadOutcome,
+    pub code: i64,
+    pub message: String,
+    /// Error output flutter run logged while handling the request.
+    pub errors: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    pub elapsed_ms: u128,
+}
+
+impl ReloadReport {
+    /// Interpret the result of `app.restart` as relayed by the supervisor.
+    pub fn from_result(
+        action: &'static str,
+        result: &serde_json::Value,
+        elapsed: std::time::Duration,
+    ) -> Self {
+        let code = result.get("code").and_then(|c| c.as_i64()).unwrap_or(0);
+        let message = result
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("")
+            .to_string();
+        let errors: Vec<String> = result
+            .get("errors")
+            .and_then(|e| e.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let hint = result
+            .get("hintMessage")
+            .and_then(|h| h.as_str())
+            .map(String::from);
+
+        ReloadReport {
+            action,
+            outcome: classify(code, &message, &errors),
+            code,
+            message,
+            errors,
+            hint,
+            elapsed_ms: elapsed.as_millis(),
+        }
+    }
+
+    /// Interpret the VM Service's `ReloadReport` from `reloadSources`, used
+    /// without flutter run. The VM explains a rejected reload in its notices.
+    pub fn from_reload_sources(
+        action: &'static str,
+        report: &serde_json::Value,
+        elapsed: std::time::Duration,
+    ) -> Self {
+        let success = report.get("success").and_then(|s| s.as_bool()) == Some(true);
+        let reasons: Vec<String> = report
+            .get("notices")
+            .and_then(|n| n.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|notice| notice.get("message").and_then(|m| m.as_str()))
+            .map(String::from)
+            .collect();
+        let outcome = if success {
+            ReloadOutcome::Success
+        } else if reasons.iter().any(|r| r.contains("Error: ")) {
+            ReloadOutcome::CompileError
+        } else {
+            ReloadOutcome::RestartRequired
+        };
+        let (message, errors) = match outcome {
+            ReloadOutcome::Success => (
+                "Reloaded sources through the VM Service".to_string(),
+                reasons,
+            ),
+            ReloadOutcome::CompileError => (String::new(), reasons),
+            _ => (reasons.join("\n"), Vec::new()),
+        };
+        ReloadReport {
+            action,
+            outcome,
+            code: if success { 0 } else { 1 },
+            message,
+            errors,
+            hint: None,
+            elapsed_ms: elapsed.as_millis(),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.outcome == ReloadOutcome::Success
+    }
+
+    pub fn format(&self) -> String {
+        let elapsed = format!("{:.1}s", self.elapsed_ms as f64 / 1000.0);
+        let mut out = match self.outcome {
+            ReloadOutcome::Success => format!("{} succeeded in {elapsed}", self.action),
+            ReloadOutcome::CompileError => {
+                format!("{} failed in {elapsed}: compilation errors", self.action)
+            }
+            ReloadOutcome::RestartRequired => {
+                format!("{} rejected in {elapsed}: restart required", self.action)
+            }
+            ReloadOutcome::Failed => {
+                format!("{} failed in {elapsed} (code {})", self.action, self.code)
+            }
+        };
+        if !self.message.is_empty() {
+            out.push_str(&format!("\n  {}", self.message.trim_end()));
+        }
+        for error in &self.errors {
+            for line in error.trim_end().lines() {
+                out.push_str(&format!("\n  {line}"));
+            }
+        }
+        if let Some(ref hint) = self.hint {
+            out.push_str(&format!("\n  Hint: {hint}"));
+        }
+        out
+    }
+}
+
+fn classify(code: i64, message: &str, errors: &[String]) -> ReloadOutcome {
+    if code == 0 {
+        return ReloadOutcome::Success;
+    }
+    let message = message.to_ascii_lowercase();
+    if message.contains("rejected") || message.contains("restart required") {
+        return ReloadOutcome::RestartRequired;
+    }
+    if !errors.is_empty()
+        || message.contains("compil")
+        || message.contains("fixing the above error")
+    {
+        return ReloadOutcome::CompileError;
+    }
+    ReloadOutcome::Failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn success() {
+        let result = serde_json::json!({ "code": 0, "message": "Reloaded 3 of 812 libraries" });
+        let report = ReloadReport::from_result("Hot reload", &result, Duration::from_millis(420));
+        assert!(report.succeeded());
+        assert_eq!(
+            report.format(),
+            "Hot reload succeeded in 0.4s\n  Reloaded 3 of 812 libraries"
+        );
+    }
+
+    #[test]
+    fn compile_errors() {
+        let result = serde_json::json!({
+            "code": 1,
+            "message": "Try again after fixing the above error(s).",
+            "errors": ["lib/main.dart:12:3: Error: Expected ';' after this."],
+        });
+        let report = ReloadReport::from_result("Hot reload", &result, Duration::from_millis(1250));
+        assert_eq!(report.outcome, ReloadOutcome::CompileError);
+        assert_eq!(
+            report.format(),
+            "Hot reload failed in 1.2s: compilation errors\n\
+             \x20\x20Try again after fixing the above error(s).\n\
+             \x20\x20lib/main.dart:12:3: Error: Expected ';' after this."
+        );
+    }
+
+    #[test]
+    fn restart_required() {
+        let result = serde_json::json!({
+            "code": 1,
+            "message": "Hot reload was rejected:\nConst class cannot remove fields",
+        });
+        let report = ReloadReport::from_result("Hot reload", &result, Duration::ZERO);
+        assert_eq!(report.outcome, ReloadOutcome::RestartRequired);
+        assert!(!report.succeeded());
+    }
+
+    #[test]
+    fn reload_sources_report() {
+        let ok = serde_json::json!({ "type": "ReloadReport", "success": true });
+        let report = ReloadReport::from_reload_sources("Hot reload", &ok, Duration::ZERO);
+        assert!(report.succeeded());
+
+        let compile_error = serde_json::json!({
+            "type": "ReloadReport",
+            "success": false,
+            "notices": [{ "type": "ReasonForCancelling",
+                          "message": "lib/main.dart:12:3: Error: Expected ';' after this." }],
+        });
+        let report =
+            ReloadReport::from_reload_sources("Hot reload", &compile_error, Duration::ZERO);
+        assert_eq!(report.outcome, ReloadOutcome::CompileError);
+        assert_eq!(report.errors.len(), 1);
+
+        let rejected = serde_json::json!({
+            "type": "ReloadReport",
+            "success": false,
+            "notices": [{ "type": "ReasonForCancelling",
+                          "message": "Const class cannot remove fields: Library:'package:app/main.dart' Class: Config" }],
+        });
+        let report = ReloadReport::from_reload_sources("Hot reload", &rejected, Duration::ZERO);
+        assert_eq!(report.outcome, ReloadOutcome::RestartRequired);
+        assert!(report.format().contains("Const class cannot remove fields"));
+    }
+
+    #[test]
+    fn other_failure() {
+        let result = serde_json::json!({ "code": 2, "message": "Device disconnected" });
+        let report = ReloadReport::from_result("Hot restart", &result, Duration::ZERO);
+        assert_eq!(report.outcome, ReloadOutcome::Failed);
+        assert_eq!(
+            serde_json::json!(report)["outcome"],
+            serde_json::json!("failed")
+        );
+    }
+}
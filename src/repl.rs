@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow};
+use clap::{CommandFactory, Parser};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+
+use crate::commands::{self, AlreadyReported};
+use crate::session::Session;
+use crate::state;
+use crate::{Cli, run_in_session};
+
+/// Read commands line by line and run them over one session until `exit` or EOF.
+pub async fn run(mut session: Session, json: bool) -> Result<()> {
+    session.watch_isolate_exits().await?;
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(ReplHelper {
+        commands: command_names(),
+        value_ids: Vec::new(),
+    }));
+    let history = state::runtime_file_path(&session.project_dir, "history");
+    let _ = editor.load_history(&history);
+
+    println!("Connected. Enter commands as on the command line, `help` or `exit`.");
+    loop {
+        // Read on a blocking thread so that the connection keeps receiving
+        // events (an exiting isolate, say) while the prompt waits
+        let (returned, line) = tokio::task::spawn_blocking(move || {
+            let line = editor.readline("flutter> ");
+            (editor, line)
+        })
+        .await?;
+        editor = returned;
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+
+        let words = match split_line(line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            }
+        };
+        let cli = match Cli::try_parse_from(std::iter::once("flutter-cli".to_string()).chain(words))
+        {
+            Ok(cli) => cli,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+        if cli.url.is_some() || cli.project_dir.is_some() || cli.device.is_some() {
+            eprintln!("--url, --project-dir and --device are fixed for the session");
+            continue;
+        }
+        if let Some(selector) = cli.isolate
+            && let Err(e) = session.select_isolate(selector)
+        {
+            eprintln!("Error: {e:#}");
+            continue;
+        }
+
+        match run_in_session(&mut session, cli.command, json || cli.json).await {
+            Ok(()) => {}
+            Err(e) if e.is::<AlreadyReported>() => {}
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                // The app may have gone away; reconnect for the next command
+                if !session.conn.ping().await
+                    && let Err(e) = session.reconnect().await
+                {
+                    eprintln!("Reconnect failed: {e:#}");
+                }
+            }
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.value_ids.clone_from(&session.value_ids);
+        }
+    }
+
+    if !session.temporary_flags.is_empty() {
+        commands::restore_debug_flags(&mut session).await;
+    }
+    if let Some(parent) = history.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    editor.save_history(&history)?;
+    Ok(())
+}
+
+/// Visible subcommand names, plus the REPL's own `exit`.
+fn command_names() -> Vec<String> {
+    let mut names: Vec<String> = Cli::command()
+        .get_subcommands()
+        .filter(|c| !c.is_hide_set())
+        .map(|c| c.get_name().to_string())
+        .collect();
+    names.extend(["help".to_string(), "exit".to_string()]);
+    names
+}
+
+/// Split a line into words like a shell: quotes group words, backslash escapes.
+fn split_line(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => word.get_or_insert_default().push(c),
+            ('\\', _) => {
+                let escaped = chars.next().ok_or_else(|| anyhow!("Trailing backslash"))?;
+                word.get_or_insert_default().push(escaped);
+            }
+            (q, None) if q == '"' || q == '\'' => {
+                quote = Some(q);
+                word.get_or_insert_default();
+            }
+            (q, Some(open)) if q == open => quote = None,
+            (c, None) if c.is_whitespace() => words.extend(word.take()),
+            (c, _) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Unterminated quote"));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Completions for the word before the cursor: command names in first
+/// position, value IDs from the last snapshot after that.
+fn complete(line: &str, commands: &[String], value_ids: &[String]) -> (usize, Vec<String>) {
+    let start = line
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + line[i..].chars().next().unwrap().len_utf8());
+    let word = &line[start..];
+    if line[..start].trim().is_empty() {
+        let matches = commands.iter().filter(|c| c.starts_with(word)).cloned();
+        return (start, matches.collect());
+    }
+
+    let (prefix, id) = match word.strip_prefix("id:") {
+        Some(id) => ("id:", id),
+        None => ("", word),
+    };
+    let matches = value_ids
+        .iter()
+        .filter(|v| v.starts_with(id))
+        .map(|v| format!("{prefix}{v}"));
+    (start, matches.collect())
+}
+
+struct ReplHelper {
+    commands: Vec<String>,
+    value_ids: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&line[..pos], &self.commands, &self.value_ids))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_quoted_words() {
+        assert_eq!(
+            split_line(r#"tap "Sign in"  --json"#).unwrap(),
+            vec!["tap", "Sign in", "--json"]
+        );
+        assert_eq!(
+            split_line(r#"eval 'a\b' x\ y "say \"hi\"" ''"#).unwrap(),
+            vec!["eval", r"a\b", "x y", r#"say "hi""#, ""]
+        );
+        assert!(split_line("tap \"Sign in").is_err());
+    }
+
+    #[test]
+    fn complete_commands_then_value_ids() {
+        let commands = vec![
+            "snapshot".to_string(),
+            "scroll".to_string(),
+            "tap".to_string(),
+        ];
+        let ids = vec!["inspector-3".to_string(), "inspector-31".to_string()];
+
+        assert_eq!(
+            complete("sc", &commands, &ids),
+            (0, vec!["scroll".to_string()])
+        );
+        assert_eq!(
+            complete("details inspector-3", &commands, &ids),
+            (
+                8,
+                vec!["inspector-3".to_string(), "inspector-31".to_string()]
+            )
+        );
+        assert_eq!(
+            complete("tap id:inspector-31", &commands, &ids),
+            (4, vec!["id:inspector-31".to_string()])
+        );
+        assert!(complete("tap Sign", &commands, &ids).1.is_empty());
+    }
+}
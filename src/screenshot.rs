@@ -0,0 +1,317 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use crate::eval::{self, dart_string};
+use crate::vm_service::VmServiceConnection;
+
+/// What a screenshot captures.
+#[derive(clap::ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The widget subtree as rendered by the inspector
+    #[default]
+    Widget,
+    /// The whole rendered frame, including overlays and platform views
+    Window,
+    /// The whole frame as a Skia picture (.skp) for rendering debugging
+    Skp,
+}
+
+/// How long to wait for the app to encode a window screenshot.
+const WINDOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Logical size and pixel ratio of a screenshot. Unset fields fall back to
+/// `.flutter-cli.toml`, then to what the app reports.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Dimensions {
+    #[serde(default)]
+    pub width: Option<f64>,
+    #[serde(default)]
+    pub height: Option<f64>,
+    #[serde(default)]
+    pub pixel_ratio: Option<f64>,
+}
+
+impl Dimensions {
+    /// Fill the fields left unset from `fallback`.
+    pub fn or(self, fallback: Dimensions) -> Dimensions {
+        Dimensions {
+            width: self.width.or(fallback.width),
+            height: self.height.or(fallback.height),
+            pixel_ratio: self.pixel_ratio.or(fallback.pixel_ratio),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.width.is_some() && self.height.is_some() && self.pixel_ratio.is_some()
+    }
+
+    /// Parameters for `ext.flutter.inspector.screenshot`. The inspector scales
+    /// the widget to fit `width` x `height` physical pixels, up to
+    /// `maxPixelRatio`, so the logical size is multiplied out here.
+    fn screenshot_params(&self) -> Result<serde_json::Value> {
+        let (Some(width), Some(height), Some(ratio)) = (self.width, self.height, self.pixel_ratio)
+        else {
+            return Err(anyhow!("Screenshot size is unknown"));
+        };
+        if width <= 0.0 || height <= 0.0 || ratio <= 0.0 {
+            return Err(anyhow!(
+                "Screenshot size must be positive, got {width}x{height} at {ratio}x"
+            ));
+        }
+        Ok(serde_json::json!({
+            "width": width * ratio,
+            "height": height * ratio,
+            "maxPixelRatio": ratio,
+        }))
+    }
+}
+
+/// Render a widget to PNG bytes at the given (possibly partial) dimensions,
+/// asking the app for its real size and pixel ratio where unset.
+pub async fn capture(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    id: Option<&str>,
+    dimensions: Dimensions,
+) -> Result<Vec<u8>> {
+    let dimensions = if dimensions.is_complete() {
+        dimensions
+    } else {
+        dimensions.or(query_dimensions(conn, isolate_id, id).await?)
+    };
+
+    let mut params = dimensions.screenshot_params()?;
+    params["isolateId"] = serde_json::json!(isolate_id);
+    if let Some(id) = id {
+        params["id"] = serde_json::json!(id);
+    }
+
+    let result = conn
+        .send("ext.flutter.inspector.screenshot", params)
+        .await?;
+
+    let image_data = result
+        .get("screenshot")
+        .and_then(|s| s.as_str())
+        .context("No screenshot data in response")?;
+
+    use base64::Engine;
+    Ok(base64::engine::general_purpose::STANDARD.decode(image_data)?)
+}
+
+/// Capture the whole frame as PNG bytes. Goes through the engine's raster
+/// screenshot, which includes platform views, and falls back to rendering
+/// the root layer in the app where the engine doesn't offer it (Impeller, web).
+pub async fn capture_window(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    pixel_ratio: Option<f64>,
+) -> Result<Vec<u8>> {
+    // The engine always captures at the device's pixel ratio
+    if pixel_ratio.is_none()
+        && let Ok(bytes) = engine_screenshot(conn, "_flutter.screenshot", "screenshot").await
+    {
+        return Ok(bytes);
+    }
+    render_root_layer(conn, isolate_id, pixel_ratio).await
+}
+
+/// Capture the last frame's layer tree as an SKP picture.
+pub async fn capture_skp(conn: &mut VmServiceConnection) -> Result<Vec<u8>> {
+    engine_screenshot(conn, "_flutter.screenshotSkp", "skp")
+        .await
+        .context("The engine cannot capture SKP pictures (not available with Impeller or on web)")
+}
+
+/// Call one of the engine's screenshot methods and decode its base64 payload.
+async fn engine_screenshot(
+    conn: &mut VmServiceConnection,
+    method: &str,
+    field: &str,
+) -> Result<Vec<u8>> {
+    let result = conn.send(method, serde_json::json!({})).await?;
+    let data = result
+        .get(field)
+        .and_then(|s| s.as_str())
+        .with_context(|| format!("No {field} data in {method} response"))?;
+
+    use base64::Engine;
+    Ok(base64::engine::general_purpose::STANDARD.decode(data)?)
+}
+
+/// Render the root view's layer tree to a PNG inside the app. Encoding is
+/// asynchronous there, so the expression hands back a one-element list that
+/// is filled in later, and we poll it.
+async fn render_root_layer(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    pixel_ratio: Option<f64>,
+) -> Result<Vec<u8>> {
+    let library = eval::library_id(conn, isolate_id, eval::INSPECTOR_LIBRARY).await?;
+    let holder =
+        eval::evaluate(conn, isolate_id, &library, &render_expression(pixel_ratio)).await?;
+    let holder_id = holder
+        .get("id")
+        .and_then(|i| i.as_str())
+        .context("Window screenshot returned no result holder")?
+        .to_string();
+
+    let start = Instant::now();
+    let encoded = loop {
+        let value = eval::evaluate_string(conn, isolate_id, &holder_id, "this.first ?? ''").await?;
+        if let Some(error) = value.strip_prefix("error:") {
+            return Err(anyhow!("Cannot capture the window: {error}"));
+        }
+        if !value.is_empty() {
+            break value;
+        }
+        if start.elapsed() > WINDOW_TIMEOUT {
+            return Err(anyhow!(
+                "Timed out after {}s waiting for the window screenshot",
+                WINDOW_TIMEOUT.as_secs()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+
+    use base64::Engine;
+    Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?)
+}
+
+/// Build an expression that starts encoding the root layer as a PNG and
+/// returns a `List<String?>` receiving the base64 data or `error:<reason>`.
+fn render_expression(pixel_ratio: Option<f64>) -> String {
+    let ratio = pixel_ratio.map_or_else(|| "dpr".to_string(), |r| format!("{r:?}"));
+    // The root layer already scales logical pixels by the device pixel ratio
+    format!(
+        "(() {{ \
+         final views = WidgetsBinding.instance.renderViews; \
+         if (views.isEmpty) return <String?>['error:the app has no view']; \
+         final view = views.first; \
+         final layer = view.debugLayer; \
+         if (layer is! OffsetLayer) return <String?>['error:the view has not been painted']; \
+         final dpr = view.flutterView.devicePixelRatio; \
+         final out = <String?>[null]; \
+         layer.toImage(Offset.zero & (view.size * dpr), pixelRatio: {ratio} / dpr) \
+           .then((image) => image.toByteData(format: ui.ImageByteFormat.png)) \
+           .then((data) {{ out[0] = base64.encode(data!.buffer.asUint8List()); }}, \
+             onError: (Object e) {{ out[0] = 'error:$e'; }}); \
+         return out; \
+         }})()"
+    )
+}
+
+/// Logical size of the widget's render box (or the root view) and the
+/// device pixel ratio of the view showing it.
+async fn query_dimensions(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+    id: Option<&str>,
+) -> Result<Dimensions> {
+    let library = eval::library_id(conn, isolate_id, eval::INSPECTOR_LIBRARY).await?;
+    let result =
+        eval::evaluate_string(conn, isolate_id, &library, &dimensions_expression(id)).await?;
+    let value: serde_json::Value = serde_json::from_str(&result)?;
+    if let Some(error) = value.get("error").and_then(|e| e.as_str()) {
+        return Err(anyhow!("Cannot size the screenshot: {error}"));
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Build an expression returning `{width, height, pixel_ratio}` as JSON.
+fn dimensions_expression(id: Option<&str>) -> String {
+    let target = match id {
+        Some(id) => format!(
+            "final o = WidgetInspectorService.instance.toObject({}); \
+             final ro = o is Element ? o.renderObject : o; \
+             if (ro is! RenderBox || !ro.hasSize) return json.encode({{'error': 'it is not a laid out box'}}); \
+             size = ro.size; \
+             RenderObject? node = ro; \
+             while (node != null && node is! RenderView) {{ node = node.parent; }} \
+             if (node is RenderView) view = node;",
+            dart_string(id)
+        ),
+        None => String::new(),
+    };
+    format!(
+        "(() {{ \
+         final views = WidgetsBinding.instance.renderViews; \
+         if (views.isEmpty) return json.encode({{'error': 'the app has no view'}}); \
+         var view = views.first; \
+         var size = view.size; \
+         {target} \
+         return json.encode({{'width': size.width, 'height': size.height, \
+           'pixel_ratio': view.flutterView.devicePixelRatio}}); \
+         }})()"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_config_override_app() {
+        let flags = Dimensions {
+            width: Some(800.0),
+            ..Dimensions::default()
+        };
+        let config = Dimensions {
+            width: Some(1024.0),
+            pixel_ratio: Some(1.0),
+            ..Dimensions::default()
+        };
+        let app = Dimensions {
+            width: Some(390.0),
+            height: Some(844.0),
+            pixel_ratio: Some(3.0),
+        };
+        let resolved = flags.or(config).or(app);
+        assert_eq!(
+            resolved,
+            Dimensions {
+                width: Some(800.0),
+                height: Some(844.0),
+                pixel_ratio: Some(1.0),
+            }
+        );
+        assert!(resolved.is_complete());
+        assert!(!flags.or(config).is_complete());
+    }
+
+    #[test]
+    fn params_in_physical_pixels() {
+        let dimensions = Dimensions {
+            width: Some(1280.0),
+            height: Some(720.0),
+            pixel_ratio: Some(1.5),
+        };
+        assert_eq!(
+            dimensions.screenshot_params().unwrap(),
+            serde_json::json!({ "width": 1920.0, "height": 1080.0, "maxPixelRatio": 1.5 })
+        );
+
+        let zero = Dimensions {
+            pixel_ratio: Some(0.0),
+            ..dimensions
+        };
+        assert!(zero.screenshot_params().is_err());
+        assert!(Dimensions::default().screenshot_params().is_err());
+    }
+
+    #[test]
+    fn render_expression_ratio() {
+        assert!(render_expression(None).contains("pixelRatio: dpr / dpr"));
+        assert!(render_expression(Some(2.0)).contains("pixelRatio: 2.0 / dpr"));
+    }
+
+    #[test]
+    fn config_keys() {
+        let dimensions: Dimensions = toml::from_str("width = 1280\npixel_ratio = 2.0").unwrap();
+        assert_eq!(dimensions.width, Some(1280.0));
+        assert_eq!(dimensions.height, None);
+        assert_eq!(dimensions.pixel_ratio, Some(2.0));
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::debug_flags::{DebugFlag, FlagValue};
+use crate::isolate;
+use crate::process;
+use crate::state::{CachedIsolate, State};
+use crate::vm_service::{EventStream, StreamId, VmServiceConnection};
+
+/// A connection to the Flutter app's VM Service, shared by the commands run over it.
+pub struct Session {
+    pub project_dir: PathBuf,
+    /// VM Service URL given with --url, if the app is not managed by us.
+    pub url: Option<String>,
+    /// Device given with --device, used if flutter run has to be started.
+    pub device: Option<String>,
+    pub conn: VmServiceConnection,
+    /// Isolate picked with --isolate; None finds the Flutter isolate.
+    isolate_selector: Option<String>,
+    isolate_id: Option<String>,
+    /// Isolate stream events, watched by long-lived sessions to notice the
+    /// isolate exiting.
+    isolate_events: Option<EventStream>,
+    /// Value IDs from the last snapshot, offered as REPL completions.
+    pub value_ids: Vec<String>,
+    /// Original values of debug settings changed with --temporary, restored
+    /// when the session ends.
+    pub temporary_flags: Vec<(DebugFlag, FlagValue)>,
+}
+
+impl Session {
+    /// Connect, using the given isolate selector or else the one remembered
+    /// for the managed process.
+    pub async fn connect(
+        project_dir: PathBuf,
+        url: Option<String>,
+        isolate: Option<String>,
+        device: Option<String>,
+    ) -> Result<Self> {
+        let conn =
+            process::ensure_connection(&project_dir, url.as_deref(), device.as_deref()).await?;
+        let mut session = Self {
+            project_dir,
+            url,
+            device,
+            conn,
+            isolate_selector: None,
+            isolate_id: None,
+            isolate_events: None,
+            value_ids: Vec::new(),
+            temporary_flags: Vec::new(),
+        };
+        match isolate {
+            Some(selector) => session.select_isolate(selector)?,
+            None => session.isolate_selector = session.managed_state()?.and_then(|s| s.isolate),
+        }
+        Ok(session)
+    }
+
+    /// Pick the isolate for later commands, remembering it in the managed
+    /// process's state. `auto` goes back to finding the Flutter isolate.
+    pub fn select_isolate(&mut self, selector: String) -> Result<()> {
+        let selector = (selector != isolate::AUTO).then_some(selector);
+        if let Some(mut state) = self.managed_state()?
+            && state.isolate != selector
+        {
+            state.isolate.clone_from(&selector);
+            state.cached_isolate = None;
+            state.save(&self.project_dir)?;
+        }
+        self.isolate_selector = selector;
+        self.isolate_id = None;
+        Ok(())
+    }
+
+    /// State of the managed flutter run process. None when connected with --url.
+    pub fn managed_state(&self) -> Result<Option<State>> {
+        if self.url.is_some() {
+            return Ok(None);
+        }
+        State::load(&self.project_dir)
+    }
+
+    /// Open a fresh connection, restarting flutter run if it has gone away.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.conn = process::ensure_connection(
+            &self.project_dir,
+            self.url.as_deref(),
+            self.device.as_deref(),
+        )
+        .await?;
+        self.isolate_id = None;
+        if self.isolate_events.is_some() {
+            self.isolate_events = None;
+            self.watch_isolate_exits().await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribe to isolate events so that an exiting isolate is dropped
+    /// before the next command, for sessions running many commands.
+    pub async fn watch_isolate_exits(&mut self) -> Result<()> {
+        self.isolate_events = Some(self.conn.stream_listen(StreamId::Isolate).await?);
+        Ok(())
+    }
+
+    /// The selected (or else the Flutter) isolate's ID. The isolate found on
+    /// an earlier run is reused while its start time still matches; otherwise
+    /// it is looked up and remembered in the managed process's state.
+    pub async fn isolate_id(&mut self) -> Result<String> {
+        self.check_isolate_exits().await?;
+        if let Some(ref id) = self.isolate_id {
+            return Ok(id.clone());
+        }
+
+        let state = self.managed_state()?;
+        if let Some(cached) = state.as_ref().and_then(|s| s.cached_isolate.as_ref())
+            && isolate::start_time(&mut self.conn, &cached.id).await == Some(cached.start_time)
+        {
+            self.isolate_id = Some(cached.id.clone());
+            return Ok(cached.id.clone());
+        }
+
+        let id = match self.isolate_selector {
+            Some(ref selector) => isolate::select_isolate(&mut self.conn, selector).await?,
+            None => isolate::find_flutter_isolate(&mut self.conn).await?,
+        };
+        if let Some(mut state) = state
+            && let Some(start_time) = isolate::start_time(&mut self.conn, &id).await
+        {
+            state.cached_isolate = Some(CachedIsolate {
+                id: id.clone(),
+                start_time,
+            });
+            state.save(&self.project_dir)?;
+        }
+        self.isolate_id = Some(id.clone());
+        Ok(id)
+    }
+
+    /// Forget the isolate if an `IsolateExit` event arrived for it.
+    async fn check_isolate_exits(&mut self) -> Result<()> {
+        if self.isolate_events.is_none() {
+            return Ok(());
+        }
+        // The VM answers in order, so after a round trip every event it sent
+        // before has been read off the socket
+        self.conn.ping().await;
+        let Some(events) = self.isolate_events.as_mut() else {
+            return Ok(());
+        };
+        let mut exited = false;
+        while let Some(event) = events.try_next() {
+            exited |= event.kind == "IsolateExit"
+                && event.isolate_id.is_some()
+                && event.isolate_id == self.isolate_id;
+        }
+        if exited {
+            self.forget_isolate()?;
+        }
+        Ok(())
+    }
+
+    pub fn isolate_selector(&self) -> Option<&str> {
+        self.isolate_selector.as_deref()
+    }
+
+    /// Forget the isolate, here and in the state cache, e.g. after a hot
+    /// restart replaced it.
+    pub fn forget_isolate(&mut self) -> Result<()> {
+        self.isolate_id = None;
+        if let Some(mut state) = self.managed_state()?
+            && state.cached_isolate.take().is_some()
+        {
+            state.save(&self.project_dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use tokio::sync::{mpsc, oneshot};
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// A VM Service with one Flutter isolate, which exits and is replaced by
+    /// `isolates/2` when asked over `exit`, acknowledged once the
+    /// `IsolateExit` event is on the socket.
+    async fn fake_vm_service(mut exit: mpsc::Receiver<oneshot::Sender<()>>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            let mut isolate = "isolates/1";
+            loop {
+                tokio::select! {
+                    Some(ack) = exit.recv() => {
+                        let event = serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "method": "streamNotify",
+                            "params": { "streamId": "Isolate", "event": {
+                                "kind": "IsolateExit", "isolate": { "id": isolate },
+                            } },
+                        });
+                        ws.send(Message::Text(event.to_string())).await.unwrap();
+                        isolate = "isolates/2";
+                        let _ = ack.send(());
+                    }
+                    Some(Ok(Message::Text(text))) = ws.next() => {
+                        let request: serde_json::Value = serde_json::from_str(&text).unwrap();
+                        let result = match request["method"].as_str() {
+                            Some("getVM") => {
+                                serde_json::json!({ "isolates": [{ "id": isolate, "name": "main" }] })
+                            }
+                            Some("getIsolate") => serde_json::json!({
+                                "id": isolate,
+                                "extensionRPCs": ["ext.flutter.reassemble"],
+                            }),
+                            _ => serde_json::json!({ "type": "Success" }),
+                        };
+                        let response =
+                            serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+                        ws.send(Message::Text(response.to_string())).await.unwrap();
+                    }
+                    else => break,
+                }
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn isolate_exit_drops_the_isolate() {
+        let (exit, exit_rx) = mpsc::channel(1);
+        let url = fake_vm_service(exit_rx).await;
+        let mut session = Session::connect(std::env::temp_dir(), Some(url), None, None)
+            .await
+            .unwrap();
+        session.watch_isolate_exits().await.unwrap();
+        assert_eq!(session.isolate_id().await.unwrap(), "isolates/1");
+
+        let (ack, acked) = oneshot::channel();
+        exit.send(ack).await.unwrap();
+        acked.await.unwrap();
+        assert_eq!(session.isolate_id().await.unwrap(), "isolates/2");
+    }
+}
@@ -1,6 +1,7 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
-use crate::isolate;
 use crate::vm_service::VmServiceConnection;
 
 #[derive(Clone)]
@@ -11,23 +12,73 @@ pub struct SnapshotOptions {
 }
 
 /// A node in the Flutter widget tree (DiagnosticsNode from the inspector protocol).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct WidgetNode {
+    #[serde(rename = "type")]
     pub widget_type: String,
     pub value_id: String,
     pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub creation_location: Option<CreationLocation>,
+    #[serde(default)]
     pub children: Vec<WidgetNode>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CreationLocation {
+    /// Full file URI as reported by the inspector.
     pub file: String,
     pub line: u32,
+    pub column: u32,
 }
 
-pub async fn get_widget_tree(conn: &mut VmServiceConnection) -> Result<Vec<WidgetNode>> {
-    let isolate_id = isolate::find_flutter_isolate(conn).await?;
+impl CreationLocation {
+    /// Just the filename, without the directory part.
+    pub fn file_name(&self) -> &str {
+        self.file.rsplit('/').next().unwrap_or(&self.file)
+    }
+
+    /// `file.dart:line`, as shown next to widgets.
+    pub fn short(&self) -> String {
+        format!("{}:{}", self.file_name(), self.line)
+    }
+}
+
+impl WidgetNode {
+    /// The text a Text widget shows, if any.
+    pub fn text(&self) -> Option<&str> {
+        if self.widget_type != "Text" || self.description.is_empty() || self.description == "Text" {
+            return None;
+        }
+        let text = self
+            .description
+            .strip_prefix("Text")
+            .unwrap_or(&self.description)
+            .trim();
+        // Remove surrounding quotes if already present
+        let text = text.trim_matches('"');
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Widget type, plus the text for Text widgets.
+    pub fn label(&self) -> String {
+        match self.text() {
+            Some(text) => format!("{} \"{}\"", self.widget_type, text),
+            None => self.widget_type.clone(),
+        }
+    }
+
+    /// Number of widgets in this subtree, including this one.
+    pub fn count(&self) -> usize {
+        1 + self.children.iter().map(WidgetNode::count).sum::<usize>()
+    }
+}
+
+pub async fn get_widget_tree(
+    conn: &mut VmServiceConnection,
+    isolate_id: &str,
+) -> Result<Vec<WidgetNode>> {
     let object_group = "flutter-cli-snapshot";
 
     let result = conn
@@ -59,6 +110,43 @@ pub async fn get_widget_tree(conn: &mut VmServiceConnection) -> Result<Vec<Widge
     })
 }
 
+/// Value IDs of every node in the tree, in depth-first order.
+pub fn value_ids(nodes: &[WidgetNode]) -> Vec<String> {
+    fn collect(node: &WidgetNode, out: &mut Vec<String>) {
+        if !node.value_id.is_empty() {
+            out.push(node.value_id.clone());
+        }
+        for child in &node.children {
+            collect(child, out);
+        }
+    }
+    let mut out = Vec::new();
+    for node in nodes {
+        collect(node, &mut out);
+    }
+    out
+}
+
+/// Write the complete tree as JSON, in the same format as `snapshot --json`.
+pub fn save_tree(path: &Path, nodes: &[WidgetNode]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(nodes)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Read a tree saved with `snapshot --save` or printed by `snapshot --json`.
+pub fn load_tree(path: &Path) -> Result<Vec<WidgetNode>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    // Full app trees nest deeper than serde_json's default limit
+    let mut de = serde_json::Deserializer::from_str(&contents);
+    de.disable_recursion_limit();
+    Vec::<WidgetNode>::deserialize(&mut de)
+        .with_context(|| format!("{} is not a saved widget tree", path.display()))
+}
+
 fn parse_diagnostics_node(value: &serde_json::Value) -> Option<WidgetNode> {
     let description = value
         .get("description")
@@ -96,32 +184,46 @@ fn parse_diagnostics_node(value: &serde_json::Value) -> Option<WidgetNode> {
     })
 }
 
-fn parse_location(loc: &serde_json::Value) -> Option<CreationLocation> {
+pub fn parse_location(loc: &serde_json::Value) -> Option<CreationLocation> {
     let file = loc.get("file").and_then(|f| f.as_str())?;
     let line = loc.get("line").and_then(|l| l.as_u64())? as u32;
-
-    // Extract just the filename from the full path
-    let filename = file.rsplit('/').next().unwrap_or(file);
+    let column = loc.get("column").and_then(|c| c.as_u64()).unwrap_or(0) as u32;
 
     Some(CreationLocation {
-        file: filename.to_string(),
+        file: file.to_string(),
         line,
+        column,
     })
 }
 
-/// Format the widget tree as indented text.
-pub fn format_tree(nodes: &[WidgetNode], opts: &SnapshotOptions) -> String {
-    let mut lines = Vec::new();
+/// Apply depth, filter and compact options, returning the trimmed tree.
+/// With a filter, each matching subtree becomes a root of its own.
+pub fn prune_tree(nodes: &[WidgetNode], opts: &SnapshotOptions) -> Vec<WidgetNode> {
+    let mut roots = Vec::new();
     for node in nodes {
         if opts.filter.is_some() {
-            collect_filtered_subtrees(node, opts, &mut lines);
+            collect_filtered_subtrees(node, opts, &mut roots);
         } else {
-            format_node(node, 0, opts, &mut lines);
+            prune_node(node, 0, opts, &mut roots);
         }
     }
+    roots
+}
+
+/// Format the widget tree as indented text.
+pub fn format_tree(nodes: &[WidgetNode], opts: &SnapshotOptions) -> String {
+    let mut lines = Vec::new();
+    for node in prune_tree(nodes, opts) {
+        format_node(&node, 0, &mut lines);
+    }
     lines.join("\n")
 }
 
+/// Format the widget tree as nested JSON, honoring the same options as `format_tree`.
+pub fn tree_to_json(nodes: &[WidgetNode], opts: &SnapshotOptions) -> serde_json::Value {
+    serde_json::json!(prune_tree(nodes, opts))
+}
+
 /// Known framework-internal widget types to skip in compact mode.
 const FRAMEWORK_WIDGETS: &[&str] = &[
     "Semantics",
@@ -215,7 +317,7 @@ fn is_framework_widget(widget_type: &str) -> bool {
     false
 }
 
-fn format_node(node: &WidgetNode, depth: usize, opts: &SnapshotOptions, lines: &mut Vec<String>) {
+fn prune_node(node: &WidgetNode, depth: usize, opts: &SnapshotOptions, out: &mut Vec<WidgetNode>) {
     if let Some(max) = opts.max_depth
         && depth > max
     {
@@ -225,27 +327,25 @@ fn format_node(node: &WidgetNode, depth: usize, opts: &SnapshotOptions, lines: &
     // Compact mode: skip framework internals, promote children
     if opts.compact && is_framework_widget(&node.widget_type) {
         for child in &node.children {
-            format_node(child, depth, opts, lines);
+            prune_node(child, depth, opts, out);
         }
         return;
     }
 
-    let indent = "  ".repeat(depth);
-    let mut line = format!("{}{}", indent, node.widget_type);
+    let mut children = Vec::new();
+    for child in &node.children {
+        prune_node(child, depth + 1, opts, &mut children);
+    }
+    out.push(WidgetNode {
+        children,
+        ..node.clone()
+    });
+}
 
+fn format_node(node: &WidgetNode, depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
     // Show text content for Text widgets
-    if node.widget_type == "Text" && !node.description.is_empty() && node.description != "Text" {
-        let text = node
-            .description
-            .strip_prefix("Text")
-            .unwrap_or(&node.description)
-            .trim();
-        if !text.is_empty() {
-            // Remove surrounding quotes if already present
-            let text = text.trim_matches('"');
-            line.push_str(&format!(" \"{}\"", text));
-        }
-    }
+    let mut line = format!("{}{}", indent, node.label());
 
     // Value ID
     if !node.value_id.is_empty() {
@@ -254,13 +354,13 @@ fn format_node(node: &WidgetNode, depth: usize, opts: &SnapshotOptions, lines: &
 
     // Source location
     if let Some(ref loc) = node.creation_location {
-        line.push_str(&format!(" {}:{}", loc.file, loc.line));
+        line.push_str(&format!(" {}", loc.short()));
     }
 
     lines.push(line);
 
     for child in &node.children {
-        format_node(child, depth + 1, opts, lines);
+        format_node(child, depth + 1, lines);
     }
 }
 
@@ -296,17 +396,17 @@ fn glob_match(pattern: &str, text: &str) -> bool {
     parts.last().is_none_or(|p| p.is_empty()) || pos == text.len()
 }
 
-fn collect_filtered_subtrees(node: &WidgetNode, opts: &SnapshotOptions, lines: &mut Vec<String>) {
+fn collect_filtered_subtrees(node: &WidgetNode, opts: &SnapshotOptions, out: &mut Vec<WidgetNode>) {
     let filter = opts.filter.as_deref().unwrap_or("");
     if name_matches_filter(&node.widget_type, filter) {
         let no_filter_opts = SnapshotOptions {
             filter: None,
             ..opts.clone()
         };
-        format_node(node, 0, &no_filter_opts, lines);
+        prune_node(node, 0, &no_filter_opts, out);
     } else {
         for child in &node.children {
-            collect_filtered_subtrees(child, opts, lines);
+            collect_filtered_subtrees(child, opts, out);
         }
     }
 }
@@ -347,6 +447,7 @@ mod tests {
             creation_location: Some(CreationLocation {
                 file: file.to_string(),
                 line,
+                column: 7,
             }),
             children,
         }
@@ -574,6 +675,106 @@ mod tests {
         assert!(!glob_match("*card", "cardnav"));
     }
 
+    #[test]
+    fn location_shows_file_name_only() {
+        let tree = vec![make_widget_with_loc(
+            "MyWidget",
+            "inspector-0",
+            "file:///home/me/app/lib/my_widget.dart",
+            42,
+            vec![],
+        )];
+        let output = format_tree(&tree, &default_opts());
+        assert_eq!(output, "MyWidget  [inspector-0] my_widget.dart:42");
+    }
+
+    #[test]
+    fn json_tree_structure() {
+        let tree = vec![make_widget(
+            "App",
+            "i0",
+            vec![make_widget_with_loc(
+                "Home",
+                "i1",
+                "file:///app/lib/home.dart",
+                10,
+                vec![],
+            )],
+        )];
+        let json = tree_to_json(&tree, &default_opts());
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "type": "App",
+                "valueId": "i0",
+                "description": "",
+                "children": [{
+                    "type": "Home",
+                    "valueId": "i1",
+                    "description": "",
+                    "creationLocation": {
+                        "file": "file:///app/lib/home.dart",
+                        "line": 10,
+                        "column": 7,
+                    },
+                    "children": [],
+                }],
+            }])
+        );
+    }
+
+    #[test]
+    fn json_tree_honors_options() {
+        let tree = vec![make_widget(
+            "App",
+            "i0",
+            vec![make_widget(
+                "Padding",
+                "i1",
+                vec![make_widget(
+                    "Card",
+                    "i2",
+                    vec![make_widget("Label", "i3", vec![])],
+                )],
+            )],
+        )];
+        let opts = SnapshotOptions {
+            max_depth: Some(1),
+            compact: true,
+            ..default_opts()
+        };
+        let json = tree_to_json(&tree, &opts);
+        assert_eq!(json[0]["children"][0]["type"], "Card");
+        assert_eq!(json[0]["children"][0]["children"], serde_json::json!([]));
+
+        let opts = SnapshotOptions {
+            filter: Some("Card".to_string()),
+            ..default_opts()
+        };
+        let json = tree_to_json(&tree, &opts);
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["type"], "Card");
+        assert_eq!(json[0]["children"][0]["type"], "Label");
+    }
+
+    #[test]
+    fn save_and_load_deep_tree() {
+        let mut node = make_widget("Text", "inspector-300", vec![]);
+        for i in (0..300).rev() {
+            node = make_widget("Padding", &format!("inspector-{i}"), vec![node]);
+        }
+        let tree = vec![node];
+        let path =
+            std::env::temp_dir().join(format!("flutter-cli-deep-{}.json", std::process::id()));
+        save_tree(&path, &tree).unwrap();
+        let loaded = load_tree(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            serde_json::to_string(&loaded.unwrap()).unwrap(),
+            serde_json::to_string(&tree).unwrap()
+        );
+    }
+
     #[test]
     fn empty_tree() {
         let output = format_tree(&[], &default_opts());
@@ -13,6 +13,28 @@ pub struct State {
     pub cwd: String,
     pub args: Vec<String>,
     pub started_at: u64,
+    /// PID of the supervisor owning the flutter run process.
+    #[serde(default)]
+    pub supervisor_pid: Option<u32>,
+    /// Isolate picked with --isolate (ID, number or name). None picks the
+    /// Flutter isolate automatically.
+    #[serde(default)]
+    pub isolate: Option<String>,
+    /// ID of the device the app runs on, as flutter reported it.
+    #[serde(default)]
+    pub device: Option<String>,
+    /// Isolate found for that selector on an earlier run.
+    #[serde(default)]
+    pub cached_isolate: Option<CachedIsolate>,
+}
+
+/// An isolate remembered across invocations, reused while it is still running.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedIsolate {
+    pub id: String,
+    /// The isolate's `startTime`, telling it apart from a later isolate
+    /// that reuses the ID.
+    pub start_time: i64,
 }
 
 impl State {
@@ -42,6 +64,16 @@ impl State {
         Ok(())
     }
 
+    /// Whether the app runs on `device` (an ID, or `auto` for any), as far
+    /// as is known.
+    pub fn runs_on(&self, device: &str) -> bool {
+        device == "auto"
+            || self
+                .device
+                .as_deref()
+                .is_none_or(|d| d.eq_ignore_ascii_case(device))
+    }
+
     /// Check if the PID in the state file is still alive.
     pub fn is_pid_alive(&self) -> bool {
         unsafe { libc::kill(self.pid as i32, 0) == 0 }
@@ -49,10 +81,15 @@ impl State {
 }
 
 fn state_file_path(project_dir: &Path) -> PathBuf {
+    runtime_file_path(project_dir, "json")
+}
+
+/// Path of a per-project runtime file (state, logs) with the given extension.
+pub fn runtime_file_path(project_dir: &Path, extension: &str) -> PathBuf {
     let mut hasher = Sha256::new();
     hasher.update(project_dir.to_string_lossy().as_bytes());
     let hash = hasher.finalize();
     let hex = format!("{:x}", hash);
     let short = &hex[..16];
-    PathBuf::from(STATE_DIR).join(format!("{short}.json"))
+    PathBuf::from(STATE_DIR).join(format!("{short}.{extension}"))
 }
@@ -0,0 +1,477 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::{Notify, oneshot};
+
+use crate::config::Config;
+use crate::devices::{self, Device};
+use crate::machine::{self, MachineLogEntry};
+use crate::process;
+use crate::state::{self, State};
+
+/// Long-lived process owning one `flutter run --machine` child, or `flutter
+/// attach --machine`, or a `flutter daemon` it starts the app through.
+/// CLI invocations talk to it over a Unix socket, one JSON request per connection.
+struct Supervisor {
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: Mutex<HashMap<i64, oneshot::Sender<serde_json::Value>>>,
+    next_id: AtomicI64,
+    status: Mutex<AppStatus>,
+    /// Error output collected for each `app.restart` in flight, by request
+    /// id. flutter's error logs don't say which request they belong to, so
+    /// every restart in flight gets them.
+    reload_errors: Mutex<HashMap<i64, Vec<String>>>,
+    shutdown: Notify,
+}
+
+#[derive(Serialize, Default, Clone)]
+struct AppStatus {
+    pid: u32,
+    ws_uri: Option<String>,
+    app_id: Option<String>,
+    device_id: Option<String>,
+    /// Message of the `app.progress` operation in flight, if any.
+    progress: Option<String>,
+}
+
+/// How long `flutter daemon` gets to discover the device to start the app on.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long flutter gets to answer a request, so that a client isn't left
+/// waiting when flutter hangs or crashes mid-reload.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub fn socket_path(project_dir: &Path) -> PathBuf {
+    state::runtime_file_path(project_dir, "sock")
+}
+
+/// Run the supervisor until the flutter process exits or `supervisor.stop` is received.
+/// With `attach`, it runs `flutter attach` (to `debug_url` if given) instead of `flutter run`,
+/// and with `daemon = true` in the config it starts the app through `flutter daemon`.
+/// The first line written to stdout reports startup: `{"ws_uri", "app_id"}` or `{"error"}`.
+pub async fn run(
+    project_dir: &Path,
+    device: Option<String>,
+    attach: bool,
+    debug_url: Option<&str>,
+) -> Result<()> {
+    let config = Config::load(project_dir)?.with_device(device);
+    let (args, app_start) = if attach {
+        (config.flutter_attach_args(debug_url), None)
+    } else if config.daemon {
+        let params = config.app_start_params(&project_dir.canonicalize()?)?;
+        (vec!["daemon".to_string()], Some(params))
+    } else {
+        (config.flutter_run_args(), None)
+    };
+    let tool = format!("flutter {}", args[0]);
+
+    let mut child = Command::new("flutter")
+        .args(&args)
+        .current_dir(project_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start flutter: {e}"))?;
+    let pid = child.id().context("flutter exited immediately")?;
+
+    let supervisor = Arc::new(Supervisor {
+        stdin: tokio::sync::Mutex::new(child.stdin.take().unwrap()),
+        pending: Mutex::new(HashMap::new()),
+        next_id: AtomicI64::new(1),
+        status: Mutex::new(AppStatus {
+            pid,
+            ..AppStatus::default()
+        }),
+        reload_errors: Mutex::new(HashMap::new()),
+        shutdown: Notify::new(),
+    });
+
+    let socket = socket_path(project_dir);
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket)?;
+    let mut machine_log = std::fs::File::create(process::machine_log_path(project_dir))?;
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+    let startup_deadline = tokio::time::sleep(Duration::from_secs(120));
+    tokio::pin!(startup_deadline);
+    let mut started = false;
+    let mut failure = None;
+
+    // The daemon starts the app on request, answered through the loop below
+    let (start_failed, mut start_failure) = oneshot::channel();
+    if let Some(params) = app_start {
+        let supervisor = supervisor.clone();
+        let device = config.device.clone();
+        tokio::spawn(async move {
+            if let Err(e) = supervisor.start_app(device.as_deref(), params).await {
+                let _ = start_failed.send(format!("{e:#}"));
+            }
+        });
+    }
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    break;
+                };
+                let Some(event) = machine::parse_machine_line(&line) else {
+                    continue;
+                };
+                let entry = MachineLogEntry {
+                    time: machine::now_millis(),
+                    event,
+                };
+                writeln!(machine_log, "{}", serde_json::to_string(&entry)?)?;
+                if !supervisor.handle_event(entry.event) {
+                    if !started {
+                        failure = Some("Flutter app exited during startup".to_string());
+                    }
+                    break;
+                }
+                if !started && let Some(status) = supervisor.ready_status() {
+                    save_state(project_dir, &status, &args)?;
+                    announce(&serde_json::json!({
+                        "ws_uri": status.ws_uri,
+                        "app_id": status.app_id,
+                    }));
+                    started = true;
+                }
+            }
+            conn = listener.accept() => {
+                let (stream, _) = conn?;
+                tokio::spawn(serve_client(supervisor.clone(), stream));
+            }
+            Ok(message) = &mut start_failure, if !started => {
+                failure = Some(message);
+                break;
+            }
+            _ = &mut startup_deadline, if !started => {
+                failure = Some(format!("Timeout waiting for {tool} to start (120s)"));
+                break;
+            }
+            _ = supervisor.shutdown.notified() => break,
+        }
+    }
+
+    if !started {
+        announce(&serde_json::json!({
+            "error": failure.unwrap_or_else(|| format!(
+                "{tool} exited without providing VM Service URI. Check {}",
+                process::stderr_log_path(project_dir).display()
+            )),
+        }));
+    }
+
+    // Fail requests still waiting for a response
+    supervisor.pending.lock().unwrap().clear();
+    if config.daemon && !attach {
+        supervisor.shutdown_daemon().await;
+        let _ = tokio::time::timeout(Duration::from_secs(5), child.wait()).await;
+    }
+    terminate_child(&mut child, pid).await;
+    let _ = std::fs::remove_file(&socket);
+    if let Ok(Some(state)) = State::load(project_dir)
+        && state.pid == pid
+    {
+        State::remove(project_dir)?;
+    }
+    Ok(())
+}
+
+impl Supervisor {
+    /// Track app state from a machine event. Returns false once the app has stopped.
+    fn handle_event(&self, event: serde_json::Value) -> bool {
+        // Responses to our requests carry the request id
+        if let Some(id) = event.get("id").and_then(|i| i.as_i64()) {
+            if let Some(tx) = self.pending.lock().unwrap().remove(&id) {
+                let _ = tx.send(event);
+            }
+            return true;
+        }
+
+        let params = event.get("params");
+        let param = |key: &str| {
+            params
+                .and_then(|p| p.get(key))
+                .and_then(|v| v.as_str())
+                .map(String::from)
+        };
+        let mut status = self.status.lock().unwrap();
+        match event.get("event").and_then(|e| e.as_str()) {
+            Some("app.debugPort") => {
+                status.ws_uri = param("wsUri").or(status.ws_uri.take());
+                status.app_id = param("appId").or(status.app_id.take());
+            }
+            Some("app.start") => {
+                status.app_id = param("appId").or(status.app_id.take());
+                status.device_id = param("deviceId").or(status.device_id.take());
+            }
+            Some("app.progress") => {
+                let finished = params
+                    .and_then(|p| p.get("finished"))
+                    .and_then(|f| f.as_bool())
+                    .unwrap_or(false);
+                status.progress = if finished { None } else { param("message") };
+            }
+            Some("app.log") if params.and_then(|p| p.get("error")) == Some(&true.into()) => {
+                self.record_reload_error(param("log"));
+            }
+            Some("daemon.logMessage") if param("level").as_deref() == Some("error") => {
+                self.record_reload_error(param("message"));
+            }
+            Some("app.stop") | Some("daemon.shutdown") => return false,
+            _ => {}
+        }
+        true
+    }
+
+    fn record_reload_error(&self, message: Option<String>) {
+        let Some(message) = message else {
+            return;
+        };
+        for errors in self.reload_errors.lock().unwrap().values_mut() {
+            errors.push(message.clone());
+        }
+    }
+
+    /// Forward `app.restart`, attaching the errors flutter run logged before responding.
+    async fn restart(&self, params: serde_json::Value) -> Result<serde_json::Value> {
+        let id = self.next_request_id();
+        self.reload_errors.lock().unwrap().insert(id, Vec::new());
+        let result = self.call_with_id(id, "app.restart", params).await;
+        let errors = self
+            .reload_errors
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .unwrap_or_default();
+
+        let mut result = result?;
+        if let Some(obj) = result.as_object_mut() {
+            obj.insert("errors".to_string(), serde_json::json!(errors));
+        }
+        Ok(result)
+    }
+
+    /// App status once the VM Service URI is known.
+    fn ready_status(&self) -> Option<AppStatus> {
+        let status = self.status.lock().unwrap();
+        status.ws_uri.as_ref()?;
+        Some(status.clone())
+    }
+
+    /// Start the app through `flutter daemon` on the configured device.
+    async fn start_app(&self, device: Option<&str>, mut params: serde_json::Value) -> Result<()> {
+        self.call("device.enable", serde_json::json!({})).await?;
+        let device = self.find_device(device).await?;
+        eprintln!("Starting app on {} ({})", device.name, device.id);
+        params["deviceId"] = serde_json::json!(device.id);
+        self.call("app.start", params).await?;
+        Ok(())
+    }
+
+    /// Poll the daemon's devices until the wanted one shows up; discovery
+    /// runs in the background after `device.enable`.
+    async fn find_device(&self, wanted: Option<&str>) -> Result<Device> {
+        let deadline = Instant::now() + DEVICE_TIMEOUT;
+        loop {
+            let result = self
+                .call("device.getDevices", serde_json::json!({}))
+                .await?;
+            let found: Vec<Device> = serde_json::from_value(result)?;
+            if let Some(device) = devices::pick(&found, wanted) {
+                return Ok(device.clone());
+            }
+            if Instant::now() > deadline {
+                return Err(match wanted {
+                    Some(wanted) => anyhow!("Device {wanted} not found by flutter daemon"),
+                    None => anyhow!("flutter daemon found no devices"),
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Ask the daemon to stop the app and exit. Nothing reads its output any
+    /// more, so the responses are not awaited.
+    async fn shutdown_daemon(&self) {
+        let app_id = self.status.lock().unwrap().app_id.clone();
+        if let Some(app_id) = app_id {
+            let params = serde_json::json!({ "appId": app_id });
+            let _ = self
+                .write_request(self.next_request_id(), "app.stop", params)
+                .await;
+        }
+        let params = serde_json::json!({});
+        let _ = self
+            .write_request(self.next_request_id(), "daemon.shutdown", params)
+            .await;
+    }
+
+    fn next_request_id(&self) -> i64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    async fn write_request(&self, id: i64, method: &str, params: serde_json::Value) -> Result<()> {
+        let request = serde_json::json!([{ "id": id, "method": method, "params": params }]);
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(format!("{request}\n").as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Send a request to flutter and wait for its response event.
+    async fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        self.call_with_id(self.next_request_id(), method, params)
+            .await
+    }
+
+    async fn call_with_id(
+        &self,
+        id: i64,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        if let Err(e) = self.write_request(id, method, params).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        let response = match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(response) => {
+                response.map_err(|_| anyhow!("flutter run exited before responding to {method}"))?
+            }
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                return Err(anyhow!(
+                    "flutter did not answer {method} within {}s",
+                    REQUEST_TIMEOUT.as_secs()
+                ));
+            }
+        };
+        machine::response_result(method, response)
+    }
+
+    async fn dispatch(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        match method {
+            "supervisor.status" => Ok(serde_json::json!(self.status.lock().unwrap().clone())),
+            "supervisor.stop" => {
+                self.shutdown.notify_one();
+                Ok(serde_json::json!({}))
+            }
+            "app.restart" => self.restart(params).await,
+            _ => self.call(method, params).await,
+        }
+    }
+}
+
+async fn serve_client(supervisor: Arc<Supervisor>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let Ok(Some(line)) = lines.next_line().await else {
+        return;
+    };
+
+    let response = match serde_json::from_str::<serde_json::Value>(&line) {
+        Ok(request) => {
+            let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("");
+            let params = request
+                .get("params")
+                .cloned()
+                .unwrap_or(serde_json::json!({}));
+            match supervisor.dispatch(method, params).await {
+                Ok(result) => serde_json::json!({ "result": result }),
+                Err(e) => serde_json::json!({ "error": e.to_string() }),
+            }
+        }
+        Err(e) => serde_json::json!({ "error": format!("Invalid request: {e}") }),
+    };
+    let _ = writer.write_all(format!("{response}\n").as_bytes()).await;
+}
+
+fn save_state(project_dir: &Path, status: &AppStatus, args: &[String]) -> Result<()> {
+    let state = State {
+        pid: status.pid,
+        ws_uri: status.ws_uri.clone().unwrap_or_default(),
+        app_id: status.app_id.clone(),
+        cwd: project_dir.to_string_lossy().to_string(),
+        args: args.to_vec(),
+        started_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        supervisor_pid: Some(std::process::id()),
+        device: status.device_id.clone(),
+        isolate: None,
+        cached_isolate: None,
+    };
+    state.save(project_dir)
+}
+
+/// Report startup to the CLI that spawned us, then detach stdout from its pipe.
+fn announce(message: &serde_json::Value) {
+    let mut stdout = std::io::stdout();
+    let _ = writeln!(stdout, "{message}");
+    let _ = stdout.flush();
+    if let Ok(devnull) = std::fs::OpenOptions::new().write(true).open("/dev/null") {
+        use std::os::fd::AsRawFd;
+        unsafe {
+            libc::dup2(devnull.as_raw_fd(), libc::STDOUT_FILENO);
+        }
+    }
+}
+
+async fn terminate_child(child: &mut tokio::process::Child, pid: u32) {
+    unsafe {
+        libc::kill(pid as i32, libc::SIGTERM);
+    }
+    if tokio::time::timeout(Duration::from_millis(500), child.wait())
+        .await
+        .is_err()
+    {
+        let _ = child.kill().await;
+    }
+}
+
+/// Send one request to the project's supervisor and return its result.
+pub async fn request(
+    project_dir: &Path,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value> {
+    let stream = UnixStream::connect(socket_path(project_dir))
+        .await
+        .context("Failed to reach the flutter-cli supervisor")?;
+    let (reader, mut writer) = stream.into_split();
+    let request = serde_json::json!({ "method": method, "params": params });
+    writer.write_all(format!("{request}\n").as_bytes()).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .context("Supervisor closed the connection without responding")?;
+    let mut response: serde_json::Value = serde_json::from_str(&line)?;
+    if let Some(error) = response.get("error").and_then(|e| e.as_str()) {
+        return Err(anyhow!("{error}"));
+    }
+    Ok(response
+        .get_mut("result")
+        .map(serde_json::Value::take)
+        .unwrap_or(serde_json::Value::Null))
+}
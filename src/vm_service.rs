@@ -1,12 +1,24 @@
 use anyhow::{Result, anyhow};
-use futures::{SinkExt, StreamExt};
+use futures::stream::SplitSink;
+use futures::{SinkExt, Stream, StreamExt};
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, oneshot};
 use tokio_tungstenite::tungstenite::Message;
 
+type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+type PendingMap = Arc<Mutex<HashMap<i64, oneshot::Sender<Result<serde_json::Value>>>>>;
+type StreamMap = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Event>>>>;
+
 pub struct VmServiceConnection {
-    ws: tokio_tungstenite::WebSocketStream<
-        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
-    >,
+    sink: SplitSink<WsStream, Message>,
+    pending: PendingMap,
+    streams: StreamMap,
+    reader: tokio::task::JoinHandle<()>,
     next_id: i64,
 }
 
@@ -15,11 +27,21 @@ impl VmServiceConnection {
         let (ws, _) = tokio_tungstenite::connect_async(ws_url)
             .await
             .map_err(|e| anyhow!("Failed to connect to VM Service at {ws_url}: {e}"))?;
-        Ok(Self { ws, next_id: 1 })
+        let (sink, source) = ws.split();
+        let pending = PendingMap::default();
+        let streams = StreamMap::default();
+        let reader = tokio::spawn(read_messages(source, pending.clone(), streams.clone()));
+        Ok(Self {
+            sink,
+            pending,
+            streams,
+            reader,
+            next_id: 1,
+        })
     }
 
     /// Send a JSON-RPC 2.0 request and wait for the matching response.
-    /// Skips over events (messages without an "id" field).
+    /// Events arriving in the meantime are routed to their stream subscribers.
     pub async fn send(
         &mut self,
         method: &str,
@@ -28,47 +50,315 @@ impl VmServiceConnection {
         let id = self.next_id;
         self.next_id += 1;
 
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
         let msg = serde_json::json!({
             "jsonrpc": "2.0",
             "id": id,
             "method": method,
             "params": params,
         });
-        self.ws.send(Message::Text(msg.to_string())).await?;
-
-        while let Some(msg) = self.ws.next().await {
-            if let Ok(Message::Text(text)) = msg {
-                let mut de = serde_json::Deserializer::from_str(&text);
-                de.disable_recursion_limit();
-                let resp = serde_json::Value::deserialize(&mut de)?;
-
-                // Skip events (no id field)
-                let Some(resp_id) = resp.get("id") else {
-                    continue;
-                };
-                if resp_id != &serde_json::json!(id) {
-                    continue;
-                }
-
-                if let Some(error) = resp.get("error") {
-                    let msg = error
-                        .get("message")
-                        .and_then(|m| m.as_str())
-                        .unwrap_or("unknown error");
-                    let code = error.get("code").and_then(|c| c.as_i64()).unwrap_or(0);
-                    return Err(anyhow!("VM Service error {code}: {msg}"));
-                }
-
-                return Ok(resp.get("result").cloned().unwrap_or(serde_json::json!({})));
-            }
+        if let Err(e) = self.sink.send(Message::Text(msg.to_string())).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e.into());
         }
-        Err(anyhow!("WebSocket closed without response"))
+
+        rx.await
+            .map_err(|_| anyhow!("WebSocket closed without response"))?
     }
 
     /// Check if connection is alive by sending getVersion
     pub async fn ping(&mut self) -> bool {
         self.send("getVersion", serde_json::json!({})).await.is_ok()
     }
+
+    /// Subscribe to a VM Service event stream.
+    /// Request/response calls keep working while the returned stream is alive.
+    pub async fn stream_listen(&mut self, stream: StreamId) -> Result<EventStream> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.streams
+            .lock()
+            .unwrap()
+            .insert(stream.as_str().to_string(), tx);
+
+        let result = self
+            .send(
+                "streamListen",
+                serde_json::json!({ "streamId": stream.as_str() }),
+            )
+            .await;
+        if let Err(e) = result {
+            self.streams.lock().unwrap().remove(stream.as_str());
+            return Err(e);
+        }
+        Ok(EventStream { rx })
+    }
+
+    /// Unsubscribe from a VM Service event stream. Its `EventStream` ends.
+    // Commands keep their streams until the connection closes
+    #[allow(dead_code)]
+    pub async fn stream_cancel(&mut self, stream: StreamId) -> Result<()> {
+        self.streams.lock().unwrap().remove(stream.as_str());
+        self.send(
+            "streamCancel",
+            serde_json::json!({ "streamId": stream.as_str() }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl Drop for VmServiceConnection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Read incoming messages, completing pending requests and dispatching events.
+async fn read_messages(
+    mut source: futures::stream::SplitStream<WsStream>,
+    pending: PendingMap,
+    streams: StreamMap,
+) {
+    while let Some(msg) = source.next().await {
+        let Ok(Message::Text(text)) = msg else {
+            continue;
+        };
+        let mut de = serde_json::Deserializer::from_str(&text);
+        de.disable_recursion_limit();
+        let Ok(msg) = serde_json::Value::deserialize(&mut de) else {
+            continue;
+        };
+
+        // Events (no id field) are delivered as streamNotify notifications
+        let Some(id) = msg.get("id") else {
+            dispatch_event(&msg, &streams);
+            continue;
+        };
+        let Some(id) = id.as_i64() else {
+            continue;
+        };
+        let Some(tx) = pending.lock().unwrap().remove(&id) else {
+            continue;
+        };
+        let _ = tx.send(parse_response(msg));
+    }
+    // Dropping the senders wakes up anyone still waiting
+    pending.lock().unwrap().clear();
+    streams.lock().unwrap().clear();
+}
+
+fn parse_response(mut resp: serde_json::Value) -> Result<serde_json::Value> {
+    if let Some(error) = resp.get("error") {
+        let msg = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("unknown error");
+        let code = error.get("code").and_then(|c| c.as_i64()).unwrap_or(0);
+        return Err(anyhow!("VM Service error {code}: {msg}"));
+    }
+    Ok(resp
+        .get_mut("result")
+        .map(serde_json::Value::take)
+        .unwrap_or(serde_json::json!({})))
+}
+
+fn dispatch_event(msg: &serde_json::Value, streams: &StreamMap) {
+    if msg.get("method").and_then(|m| m.as_str()) != Some("streamNotify") {
+        return;
+    }
+    let Some(params) = msg.get("params") else {
+        return;
+    };
+    let Some(stream_id) = params.get("streamId").and_then(|s| s.as_str()) else {
+        return;
+    };
+    let Some(event) = params.get("event") else {
+        return;
+    };
+
+    let mut streams = streams.lock().unwrap();
+    if let Some(tx) = streams.get(stream_id)
+        && tx.send(Event::parse(stream_id, event)).is_err()
+    {
+        // Subscriber went away
+        streams.remove(stream_id);
+    }
+}
+
+/// VM Service event streams that can be subscribed to with `streamListen`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamId {
+    // Streams no command subscribes to yet
+    #[allow(dead_code)]
+    Extension,
+    Logging,
+    Stdout,
+    Stderr,
+    Isolate,
+    #[allow(dead_code)]
+    Gc,
+    #[allow(dead_code)]
+    Debug,
+}
+
+impl StreamId {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamId::Extension => "Extension",
+            StreamId::Logging => "Logging",
+            StreamId::Stdout => "Stdout",
+            StreamId::Stderr => "Stderr",
+            StreamId::Isolate => "Isolate",
+            StreamId::Gc => "GC",
+            StreamId::Debug => "Debug",
+        }
+    }
+}
+
+/// An event received on one of the VM Service streams.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// Event kind, e.g. `Extension`, `Logging`, `WriteEvent`, `IsolateExit`.
+    pub kind: String,
+    pub isolate_id: Option<String>,
+    /// Milliseconds since the epoch.
+    pub timestamp: Option<i64>,
+    pub data: EventData,
+}
+
+#[derive(Debug, Clone)]
+pub enum EventData {
+    #[allow(dead_code)]
+    Extension {
+        extension_kind: String,
+        data: serde_json::Value,
+    },
+    Logging(LogRecord),
+    Stdout(String),
+    Stderr(String),
+    Isolate,
+    Gc,
+    #[allow(dead_code)]
+    Debug(serde_json::Value),
+    #[allow(dead_code)]
+    Other(serde_json::Value),
+}
+
+/// A `developer.log` record from the Logging stream.
+#[derive(Debug, Clone, Default)]
+pub struct LogRecord {
+    pub message: String,
+    pub logger_name: String,
+    pub level: i64,
+    /// Orders records logged within the same millisecond.
+    #[allow(dead_code)]
+    pub sequence_number: i64,
+    pub error: Option<String>,
+    pub stack_trace: Option<String>,
+}
+
+impl Event {
+    pub fn parse(stream_id: &str, event: &serde_json::Value) -> Self {
+        let kind = event
+            .get("kind")
+            .and_then(|k| k.as_str())
+            .unwrap_or("")
+            .to_string();
+        let isolate_id = event
+            .get("isolate")
+            .and_then(|i| i.get("id"))
+            .and_then(|i| i.as_str())
+            .map(String::from);
+        let timestamp = event.get("timestamp").and_then(|t| t.as_i64());
+
+        let data = match stream_id {
+            "Extension" => EventData::Extension {
+                extension_kind: event
+                    .get("extensionKind")
+                    .and_then(|k| k.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                data: event
+                    .get("extensionData")
+                    .cloned()
+                    .unwrap_or(serde_json::Value::Null),
+            },
+            "Logging" => EventData::Logging(parse_log_record(event.get("logRecord"))),
+            "Stdout" => EventData::Stdout(decode_bytes(event)),
+            "Stderr" => EventData::Stderr(decode_bytes(event)),
+            "Isolate" => EventData::Isolate,
+            "GC" => EventData::Gc,
+            "Debug" => EventData::Debug(event.clone()),
+            _ => EventData::Other(event.clone()),
+        };
+
+        Event {
+            kind,
+            isolate_id,
+            timestamp,
+            data,
+        }
+    }
+}
+
+fn parse_log_record(record: Option<&serde_json::Value>) -> LogRecord {
+    let Some(record) = record else {
+        return LogRecord::default();
+    };
+    let int = |key: &str| record.get(key).and_then(|v| v.as_i64()).unwrap_or(0);
+    LogRecord {
+        message: instance_string(record.get("message")).unwrap_or_default(),
+        logger_name: instance_string(record.get("loggerName")).unwrap_or_default(),
+        level: int("level"),
+        sequence_number: int("sequenceNumber"),
+        error: instance_string(record.get("error")),
+        stack_trace: instance_string(record.get("stackTrace")),
+    }
+}
+
+/// The string value of an `InstanceRef`, or None for null instances.
+fn instance_string(instance: Option<&serde_json::Value>) -> Option<String> {
+    let instance = instance?;
+    if instance.get("kind").and_then(|k| k.as_str()) == Some("Null") {
+        return None;
+    }
+    instance
+        .get("valueAsString")
+        .and_then(|v| v.as_str())
+        .map(String::from)
+}
+
+/// Decode the base64 `bytes` payload of a WriteEvent.
+fn decode_bytes(event: &serde_json::Value) -> String {
+    use base64::Engine;
+    let bytes = event.get("bytes").and_then(|b| b.as_str()).unwrap_or("");
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(bytes)
+        .unwrap_or_default();
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Events from a `streamListen` subscription. Ends when the stream is
+/// cancelled or the connection closes.
+pub struct EventStream {
+    rx: mpsc::UnboundedReceiver<Event>,
+}
+
+impl EventStream {
+    /// An event already received, without waiting for more.
+    pub fn try_next(&mut self) -> Option<Event> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.rx.poll_recv(cx)
+    }
 }
 
 /// Try to connect to a VM Service URL with a timeout.
@@ -84,3 +374,140 @@ pub async fn try_connect(ws_url: &str, timeout_ms: u64) -> Result<VmServiceConne
         Err(_) => Err(anyhow!("Connection to {ws_url} timed out")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_logging_event() {
+        let event = serde_json::json!({
+            "kind": "Logging",
+            "isolate": { "id": "isolates/1" },
+            "timestamp": 1700000000000i64,
+            "logRecord": {
+                "message": { "kind": "String", "valueAsString": "hello" },
+                "loggerName": { "kind": "String", "valueAsString": "auth" },
+                "level": 800,
+                "sequenceNumber": 3,
+                "error": { "kind": "Null", "valueAsString": "null" },
+                "stackTrace": { "kind": "Null", "valueAsString": "null" },
+            },
+        });
+        let event = Event::parse("Logging", &event);
+        assert_eq!(event.kind, "Logging");
+        assert_eq!(event.isolate_id.as_deref(), Some("isolates/1"));
+        assert_eq!(event.timestamp, Some(1700000000000));
+        let EventData::Logging(record) = event.data else {
+            panic!("expected a log record");
+        };
+        assert_eq!(record.message, "hello");
+        assert_eq!(record.logger_name, "auth");
+        assert_eq!(record.level, 800);
+        assert_eq!(record.sequence_number, 3);
+        assert_eq!(record.error, None);
+    }
+
+    #[test]
+    fn parse_write_event() {
+        let event = serde_json::json!({
+            "kind": "WriteEvent",
+            "bytes": "aGVsbG8K",
+        });
+        let event = Event::parse("Stdout", &event);
+        assert!(matches!(event.data, EventData::Stdout(ref s) if s == "hello\n"));
+    }
+
+    #[test]
+    fn parse_extension_event() {
+        let event = serde_json::json!({
+            "kind": "Extension",
+            "extensionKind": "Flutter.Frame",
+            "extensionData": { "elapsed": 1200 },
+        });
+        let event = Event::parse("Extension", &event);
+        let EventData::Extension {
+            extension_kind,
+            data,
+        } = event.data
+        else {
+            panic!("expected an extension event");
+        };
+        assert_eq!(extension_kind, "Flutter.Frame");
+        assert_eq!(data["elapsed"], 1200);
+    }
+
+    #[test]
+    fn parse_gc_and_debug_events() {
+        let gc = Event::parse("GC", &serde_json::json!({ "kind": "GC" }));
+        assert!(matches!(gc.data, EventData::Gc));
+
+        let pause = serde_json::json!({ "kind": "PauseBreakpoint", "topFrame": { "index": 0 } });
+        let event = Event::parse("Debug", &pause);
+        assert_eq!(event.kind, "PauseBreakpoint");
+        assert!(matches!(event.data, EventData::Debug(ref e) if e["topFrame"]["index"] == 0));
+        assert!(matches!(
+            Event::parse("Timeline", &pause).data,
+            EventData::Other(_)
+        ));
+    }
+
+    /// Answer every request with `Success`, forwarding the method names and
+    /// sending an Extension event after the `streamListen`.
+    async fn fake_vm_service(methods: mpsc::UnboundedSender<String>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let request: serde_json::Value = serde_json::from_str(&text).unwrap();
+                let method = request["method"].as_str().unwrap_or("").to_string();
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0", "id": request["id"], "result": { "type": "Success" },
+                });
+                ws.send(Message::Text(response.to_string())).await.unwrap();
+                if method == "streamListen" {
+                    let event = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "method": "streamNotify",
+                        "params": { "streamId": "Extension", "event": {
+                            "kind": "Extension", "extensionKind": "Flutter.Frame",
+                        } },
+                    });
+                    ws.send(Message::Text(event.to_string())).await.unwrap();
+                }
+                let _ = methods.send(method);
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn listen_and_cancel_a_stream() {
+        let (methods, mut methods_rx) = mpsc::unbounded_channel();
+        let url = fake_vm_service(methods).await;
+        let mut conn = VmServiceConnection::connect(&url).await.unwrap();
+
+        let mut events = conn.stream_listen(StreamId::Extension).await.unwrap();
+        let event = events.next().await.unwrap();
+        assert!(
+            matches!(event.data, EventData::Extension { ref extension_kind, .. } if extension_kind == "Flutter.Frame")
+        );
+
+        conn.stream_cancel(StreamId::Extension).await.unwrap();
+        assert_eq!(events.next().await.map(|e| e.kind), None);
+        assert_eq!(methods_rx.recv().await.as_deref(), Some("streamListen"));
+        assert_eq!(methods_rx.recv().await.as_deref(), Some("streamCancel"));
+    }
+
+    #[test]
+    fn error_response() {
+        let resp = serde_json::json!({
+            "id": 1,
+            "error": { "code": 113, "message": "Method not found" },
+        });
+        let err = parse_response(resp).unwrap_err();
+        assert_eq!(err.to_string(), "VM Service error 113: Method not found");
+    }
+}